@@ -0,0 +1,194 @@
+//! Sound effects. Systems push `SoundEvent`s onto the `SoundEventQueue`, and `Game` hands them to
+//! whichever `AudioBackend` it has after every step. Headless games stay quiet unless they're
+//! given a backend, like a `RecordingBackend` in tests.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+#[cfg(target_arch = "wasm32")]
+use std::collections::HashMap;
+
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+#[cfg(target_arch = "wasm32")]
+use web_sys::{AudioBuffer, AudioContext, AudioContextState};
+
+/// Something that should be heard.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum SoundEvent {
+    Fire,
+    Hit,
+    Explosion,
+    Pickup,
+    /// The Sinistar is complete and coming for the player.
+    BossRoar,
+}
+
+impl SoundEvent {
+    pub const ALL: [SoundEvent; 5] = [
+        SoundEvent::Fire,
+        SoundEvent::Hit,
+        SoundEvent::Explosion,
+        SoundEvent::Pickup,
+        SoundEvent::BossRoar,
+    ];
+
+    /// The sound's WAV file.
+    pub fn bytes(self) -> &'static [u8] {
+        match self {
+            SoundEvent::Fire => include_bytes!("sounds/fire.wav"),
+            SoundEvent::Hit => include_bytes!("sounds/hit.wav"),
+            SoundEvent::Explosion => include_bytes!("sounds/explosion.wav"),
+            SoundEvent::Pickup => include_bytes!("sounds/pickup.wav"),
+            SoundEvent::BossRoar => include_bytes!("sounds/boss_roar.wav"),
+        }
+    }
+}
+
+pub trait AudioBackend {
+    fn play(&mut self, sound: SoundEvent);
+}
+
+/// Plays nothing. Headless games start out with this.
+#[derive(Debug, Default)]
+pub struct NullBackend;
+
+impl AudioBackend for NullBackend {
+    fn play(&mut self, _sound: SoundEvent) {}
+}
+
+/// Remembers every sound instead of playing it. Clones share what they've heard, so a test can
+/// keep one and give the other to the game.
+#[derive(Clone, Debug, Default)]
+pub struct RecordingBackend(Rc<RefCell<Vec<SoundEvent>>>);
+
+impl RecordingBackend {
+    /// Every sound played so far, in order.
+    pub fn played(&self) -> Vec<SoundEvent> {
+        self.0.borrow().clone()
+    }
+
+    pub fn clear(&self) {
+        self.0.borrow_mut().clear();
+    }
+}
+
+impl AudioBackend for RecordingBackend {
+    fn play(&mut self, sound: SoundEvent) {
+        self.0.borrow_mut().push(sound);
+    }
+}
+
+/// Plays sounds through the default output device.
+#[cfg(all(feature = "native-audio", not(target_arch = "wasm32")))]
+pub struct RodioBackend {
+    // the stream stops when this is dropped
+    _stream: rodio::OutputStream,
+    handle: rodio::OutputStreamHandle,
+}
+
+#[cfg(all(feature = "native-audio", not(target_arch = "wasm32")))]
+impl RodioBackend {
+    pub fn new() -> Result<Self, rodio::StreamError> {
+        let (stream, handle) = rodio::OutputStream::try_default()?;
+        Ok(RodioBackend {
+            _stream: stream,
+            handle,
+        })
+    }
+}
+
+#[cfg(all(feature = "native-audio", not(target_arch = "wasm32")))]
+impl AudioBackend for RodioBackend {
+    fn play(&mut self, sound: SoundEvent) {
+        use rodio::Source;
+
+        let source = match rodio::Decoder::new(std::io::Cursor::new(sound.bytes())) {
+            Ok(source) => source,
+            Err(e) => {
+                warn!("Couldn't decode {:?}: {}", sound, e);
+                return;
+            }
+        };
+        if let Err(e) = self.handle.play_raw(source.convert_samples()) {
+            warn!("Couldn't play {:?}: {}", sound, e);
+        }
+    }
+}
+
+/// Plays sounds with the Web Audio API. Decoding happens in the background, so sounds played
+/// before they're ready are skipped.
+#[cfg(target_arch = "wasm32")]
+pub struct WebAudioBackend {
+    context: AudioContext,
+    buffers: Rc<RefCell<HashMap<SoundEvent, AudioBuffer>>>,
+}
+
+#[cfg(target_arch = "wasm32")]
+impl WebAudioBackend {
+    pub fn new() -> Result<Self, JsValue> {
+        let context = AudioContext::new()?;
+        let buffers = Rc::new(RefCell::new(HashMap::new()));
+        for sound in SoundEvent::ALL.iter().copied() {
+            let data = js_sys::Uint8Array::from(sound.bytes()).buffer();
+            let decoded = Rc::clone(&buffers);
+            let on_decoded = Closure::once_into_js(move |buffer: AudioBuffer| {
+                decoded.borrow_mut().insert(sound, buffer);
+            });
+            context.decode_audio_data_with_success_callback(&data, on_decoded.unchecked_ref())?;
+        }
+        Ok(WebAudioBackend { context, buffers })
+    }
+
+    fn try_play(&self, buffer: &AudioBuffer) -> Result<(), JsValue> {
+        // browsers don't let pages make noise until they've been interacted with, and by the time
+        // there's something to play, they have been
+        if self.context.state() == AudioContextState::Suspended {
+            let _ = self.context.resume()?;
+        }
+        let source = self.context.create_buffer_source()?;
+        source.set_buffer(Some(buffer));
+        source.connect_with_audio_node(&self.context.destination())?;
+        source.start()
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl AudioBackend for WebAudioBackend {
+    fn play(&mut self, sound: SoundEvent) {
+        let buffers = self.buffers.borrow();
+        let buffer = match buffers.get(&sound) {
+            Some(buffer) => buffer,
+            None => return,
+        };
+        if let Err(e) = self.try_play(buffer) {
+            warn!("Couldn't play {:?}: {:?}", sound, e);
+        }
+    }
+}
+
+/// The best backend there is for games with a window or canvas, falling back to silence.
+#[cfg(target_arch = "wasm32")]
+pub fn default_backend() -> Box<dyn AudioBackend> {
+    match WebAudioBackend::new() {
+        Ok(backend) => Box::new(backend),
+        Err(e) => {
+            warn!("No Web Audio, the game will be silent: {:?}", e);
+            Box::new(NullBackend)
+        }
+    }
+}
+#[cfg(all(feature = "native-audio", not(target_arch = "wasm32")))]
+pub fn default_backend() -> Box<dyn AudioBackend> {
+    match RodioBackend::new() {
+        Ok(backend) => Box::new(backend),
+        Err(e) => {
+            warn!("No audio device, the game will be silent: {}", e);
+            Box::new(NullBackend)
+        }
+    }
+}
+#[cfg(all(not(feature = "native-audio"), not(target_arch = "wasm32")))]
+pub fn default_backend() -> Box<dyn AudioBackend> {
+    Box::new(NullBackend)
+}
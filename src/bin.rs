@@ -1,15 +1,86 @@
-use glfw::{Action, Key, WindowEvent};
+use glfw::{Action, Key, MouseButton as GMouseButton, WindowEvent};
+use log::{error, info};
 
 mod input;
 
-use voidstar_lib::input::{InputEvent, KeyState};
+use voidstar_lib::input::{InputEvent, InputMap, KeyState, MouseButton};
+use voidstar_lib::level::Level;
+use voidstar_lib::replay::Replay;
 use voidstar_lib::Game;
 
+const QUICKSAVE: &str = "quicksave.bin";
+
+fn mouse_button(button: GMouseButton) -> Option<MouseButton> {
+    match button {
+        GMouseButton::Button1 => Some(MouseButton::Main),
+        GMouseButton::Button2 => Some(MouseButton::Secondary),
+        GMouseButton::Button3 => Some(MouseButton::Aux),
+        GMouseButton::Button4 => Some(MouseButton::Fourth),
+        GMouseButton::Button5 => Some(MouseButton::Fifth),
+        _ => None,
+    }
+}
+
+/// Exits with `message` when something the player asked for on the command line goes wrong.
+fn fail(message: String) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(1);
+}
+
 fn main() {
     env_logger::init();
-    let mut game = Game::new();
+    // usage: voidstar-bin [LEVEL] [--input FILE] [--record FILE | --replay FILE]
+    let mut level_path = None;
+    let mut record_path = None;
+    let mut replay_path = None;
+    let mut input_path = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--record" => record_path = args.next(),
+            "--replay" => replay_path = args.next(),
+            "--input" => input_path = args.next(),
+            _ => level_path = Some(arg),
+        }
+    }
+
+    let replaying = replay_path.is_some();
+    let mut game = match replay_path {
+        Some(path) => {
+            let bytes = std::fs::read(&path)
+                .unwrap_or_else(|e| fail(format!("couldn't read {}: {}", path, e)));
+            let replay =
+                Replay::from_bytes(&bytes).unwrap_or_else(|e| fail(format!("{}: {}", path, e)));
+            Game::headless_from_replay(replay).with_renderer()
+        }
+        None => {
+            // an optional level file to play instead of the built-in one
+            let level = match level_path {
+                Some(path) => {
+                    let source = std::fs::read_to_string(&path)
+                        .unwrap_or_else(|e| fail(format!("couldn't read {}: {}", path, e)));
+                    Level::parse(&source).unwrap_or_else(|e| fail(format!("{}: {}", path, e)))
+                }
+                None => Level::default(),
+            };
+            Game::with_level(&level)
+        }
+    };
+    // replays bring their own bindings
+    if let (Some(path), false) = (input_path, replaying) {
+        let source = std::fs::read_to_string(&path)
+            .unwrap_or_else(|e| fail(format!("couldn't read {}: {}", path, e)));
+        let map = InputMap::parse(&source).unwrap_or_else(|e| fail(format!("{}: {}", path, e)));
+        game.set_input_map(map);
+    }
+    if record_path.is_some() {
+        game.start_recording();
+    }
     let mut event_buf = vec![];
+    // GLFW only says where the cursor is when it moves, so button presses are placed from this
+    let mut cursor = (0, 0);
     'app: loop {
+        let (mut save, mut load) = (false, false);
         for (_, event) in game.iter_events() {
             match event {
                 // If we close the window or press escape, quit the main loop (i.e. quit the application).
@@ -17,6 +88,10 @@ fn main() {
                     break 'app
                 }
 
+                // the game is borrowed while its events are being read, so these wait until after
+                WindowEvent::Key(Key::F5, _, Action::Press, _) => save = true,
+                WindowEvent::Key(Key::F9, _, Action::Press, _) => load = true,
+
                 WindowEvent::Key(k, _, Action::Press, _) => {
                     event_buf.push(InputEvent::KeyboardEvent {
                         code: k.into(),
@@ -33,6 +108,29 @@ fn main() {
                     });
                 }
 
+                WindowEvent::CursorPos(x, y) => {
+                    cursor = (x as i32, y as i32);
+                    event_buf.push(InputEvent::MouseMoveEvent {
+                        x: cursor.0,
+                        y: cursor.1,
+                    });
+                }
+
+                WindowEvent::MouseButton(button, action, _) => {
+                    let state = match action {
+                        Action::Release => KeyState::Released,
+                        _ => KeyState::Pressed,
+                    };
+                    if let Some(button) = mouse_button(button) {
+                        event_buf.push(InputEvent::MouseEvent {
+                            button,
+                            state,
+                            x: cursor.0,
+                            y: cursor.1,
+                        });
+                    }
+                }
+
                 _ => {}
             }
         }
@@ -40,7 +138,31 @@ fn main() {
         for e in event_buf.drain(..) {
             game.log_event(e)
         }
+        game.poll_gamepad();
+
+        if save {
+            match std::fs::write(QUICKSAVE, game.save()) {
+                Ok(()) => info!("Saved to {}", QUICKSAVE),
+                Err(e) => error!("Couldn't save to {}: {}", QUICKSAVE, e),
+            }
+        }
+        if load {
+            let loaded = std::fs::read(QUICKSAVE)
+                .map_err(|e| e.to_string())
+                .and_then(|bytes| game.load(&bytes).map_err(|e| e.to_string()));
+            match loaded {
+                Ok(()) => info!("Loaded {}", QUICKSAVE),
+                Err(e) => error!("Couldn't load {}: {}", QUICKSAVE, e),
+            }
+        }
 
         game.tick();
     }
+
+    if let (Some(path), Some(replay)) = (record_path, game.finish_recording()) {
+        match std::fs::write(&path, replay) {
+            Ok(()) => info!("Recorded to {}", path),
+            Err(e) => error!("Couldn't record to {}: {}", path, e),
+        }
+    }
 }
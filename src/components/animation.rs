@@ -0,0 +1,163 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// What a clip does once it gets to its last frame.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Playback {
+    /// Starts over from the first frame.
+    Loop,
+    /// Stays on the last frame, and the animation is finished.
+    Once,
+    /// Plays backwards to the first frame, then forwards again.
+    PingPong,
+}
+
+/// A sequence of spritesheet indices, each shown for the same amount of time.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Clip {
+    pub frames: Vec<usize>,
+    pub frame_time: Duration,
+    pub playback: Playback,
+}
+
+impl Clip {
+    pub fn new(frames: Vec<usize>, frame_time: Duration, playback: Playback) -> Self {
+        assert!(!frames.is_empty(), "clips need at least one frame");
+        Clip {
+            frames,
+            frame_time,
+            playback,
+        }
+    }
+
+    /// The frame to show `elapsed` into the clip, and whether a clip that plays once is over.
+    pub fn frame_at(&self, elapsed: Duration) -> (usize, bool) {
+        let n = self.frames.len();
+        let step = if self.frame_time == Duration::default() {
+            0
+        } else {
+            (elapsed.as_nanos() / self.frame_time.as_nanos()) as usize
+        };
+        match self.playback {
+            Playback::Loop => (self.frames[step % n], false),
+            Playback::Once => (self.frames[step.min(n - 1)], step >= n),
+            Playback::PingPong if n == 1 => (self.frames[0], false),
+            Playback::PingPong => {
+                // there and back without showing either end twice in a row
+                let step = step % (2 * n - 2);
+                let i = if step < n { step } else { 2 * n - 2 - step };
+                (self.frames[i], false)
+            }
+        }
+    }
+}
+
+/// Named clips for an entity's `Sprite`, one of which is playing. The `animate` system advances
+/// it and copies the current frame into the sprite.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Animation {
+    clips: HashMap<String, Clip>,
+    current: String,
+    elapsed: Duration,
+    finished: bool,
+}
+
+impl Animation {
+    /// An animation playing `clip`, which is known as `name`.
+    pub fn new(name: &str, clip: Clip) -> Self {
+        let mut clips = HashMap::new();
+        clips.insert(name.to_string(), clip);
+        Animation {
+            clips,
+            current: name.to_string(),
+            elapsed: Duration::default(),
+            finished: false,
+        }
+    }
+
+    pub fn with_clip(mut self, name: &str, clip: Clip) -> Self {
+        self.clips.insert(name.to_string(), clip);
+        self
+    }
+
+    /// Switches to the clip called `name` and starts it from the beginning. Playing the clip
+    /// that's already playing carries on with it instead.
+    pub fn play(&mut self, name: &str) {
+        if self.current == name {
+            return;
+        }
+        if !self.clips.contains_key(name) {
+            warn!("No animation clip called {:?}", name);
+            return;
+        }
+        self.current = name.to_string();
+        self.elapsed = Duration::default();
+        self.finished = false;
+    }
+
+    /// The name of the clip that's playing.
+    pub fn current(&self) -> &str {
+        &self.current
+    }
+
+    /// Whether a clip that plays once has gotten to its end.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// The spritesheet index to show right now.
+    pub fn frame(&self) -> usize {
+        self.clip().frame_at(self.elapsed).0
+    }
+
+    /// Moves the current clip on by `dt`. Returns true on the step it finishes.
+    pub fn advance(&mut self, dt: Duration) -> bool {
+        if self.finished {
+            return false;
+        }
+        self.elapsed += dt;
+        self.finished = self.clip().frame_at(self.elapsed).1;
+        self.finished
+    }
+
+    fn clip(&self) -> &Clip {
+        &self.clips[&self.current]
+    }
+}
+
+/// Removed once its animation finishes, like an explosion.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct OneShot;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn frames_of(playback: Playback, steps: u32) -> Vec<usize> {
+        let clip = Clip::new(vec![1, 2, 3], Duration::from_millis(100), playback);
+        (0..steps)
+            .map(|i| clip.frame_at(Duration::from_millis(100) * i).0)
+            .collect()
+    }
+
+    #[test]
+    fn clips_play_their_frames_in_order() {
+        assert_eq!(frames_of(Playback::Loop, 7), vec![1, 2, 3, 1, 2, 3, 1]);
+        assert_eq!(frames_of(Playback::Once, 5), vec![1, 2, 3, 3, 3]);
+        assert_eq!(frames_of(Playback::PingPong, 7), vec![1, 2, 3, 2, 1, 2, 3]);
+    }
+
+    #[test]
+    fn one_shots_finish_after_their_last_frame() {
+        let clip = Clip::new(vec![4, 5], Duration::from_millis(100), Playback::Once);
+        let mut animation = Animation::new("boom", clip);
+        assert!(!animation.advance(Duration::from_millis(150)));
+        assert_eq!(animation.frame(), 5);
+        assert!(animation.advance(Duration::from_millis(50)));
+        assert!(animation.is_finished());
+        // only reported the once
+        assert!(!animation.advance(Duration::from_millis(100)));
+    }
+}
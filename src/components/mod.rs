@@ -1,33 +1,258 @@
+use std::time::Duration;
+
+use na::Vector2;
+use serde::{Deserialize, Serialize};
+
+use crate::particles::ParticleStyle;
+
+mod animation;
 mod sprite;
 mod transform;
 
+pub use animation::*;
 pub use sprite::*;
 pub use transform::*;
 
 bitflags! {
     #[rustfmt::ignore]
+    #[derive(Serialize, Deserialize)]
     pub struct EntityTag: u32 {
         const PLAYER     = 0b00000001;
         const ENEMY      = 0b00000010;
         const ASTEROID   = 0b00000100;
         const PROJECTILE = 0b00001000;
+        const BOSS       = 0b00010000;
+        const CRYSTAL    = 0b00100000;
 
         const ENEMY_OR_ASTEROID  = 0b00000110;
         const PLAYER_OR_ASTEROID = 0b00000101;
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub struct Player;
-#[derive(Copy, Clone, Debug)]
+
+impl Player {
+    /// What a new ship starts out with.
+    pub const HEALTH: u16 = 30;
+}
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub struct Projectile {
+    /// the side that fired it
+    pub owner: EntityTag,
     pub can_hit: EntityTag,
     pub damage: u8,
 }
 
 /// should be culled when it goes offscreen
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub struct Cull;
 
-#[derive(Copy, Clone, Debug)]
+/// removes the entity, and its rigid body if it has one, at the end of the step
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct Despawn;
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub struct Health(pub u16);
+
+/// can't be damaged until `remaining` runs out
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct Invulnerable {
+    pub remaining: Duration,
+}
+
+/// What the player has picked up. Every crystal the player is carrying is made into a sinibomb,
+/// as long as there's room for it, so there are never more bombs than crystals.
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Inventory {
+    pub crystals: u32,
+    pub bombs: u32,
+}
+
+impl Inventory {
+    pub const MAX_BOMBS: u32 = 20;
+
+    pub fn collect(&mut self) {
+        self.crystals += 1;
+        self.bombs = (self.bombs + 1).min(Self::MAX_BOMBS);
+    }
+
+    /// Uses up a bomb, and the crystal it was made from, if there is one.
+    pub fn launch(&mut self) -> bool {
+        if self.bombs > 0 {
+            self.bombs -= 1;
+            self.crystals = self.crystals.saturating_sub(1);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Takes a crystal away, and the bomb made from it, if there is one.
+    pub fn steal(&mut self) -> bool {
+        if self.crystals > 0 {
+            self.crystals -= 1;
+            self.bombs = self.bombs.min(self.crystals);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AsteroidSize {
+    Small,
+    Medium,
+    Large,
+    /// big slow rocks that the planetoid zone is full of
+    Planetoid,
+}
+
+impl AsteroidSize {
+    pub fn radius(self) -> f32 {
+        0.3 * self.scale()
+    }
+
+    pub fn scale(self) -> f32 {
+        match self {
+            AsteroidSize::Small => 0.5,
+            AsteroidSize::Medium => 1.0,
+            AsteroidSize::Large => 2.0,
+            AsteroidSize::Planetoid => 4.0,
+        }
+    }
+
+    pub fn health(self) -> u16 {
+        match self {
+            AsteroidSize::Small => 1,
+            AsteroidSize::Medium => 2,
+            AsteroidSize::Large => 4,
+            AsteroidSize::Planetoid => 12,
+        }
+    }
+
+    /// The size of the pieces this breaks into, if it's big enough to break.
+    pub fn smaller(self) -> Option<AsteroidSize> {
+        match self {
+            AsteroidSize::Small => None,
+            AsteroidSize::Medium => Some(AsteroidSize::Small),
+            AsteroidSize::Large => Some(AsteroidSize::Medium),
+            AsteroidSize::Planetoid => Some(AsteroidSize::Large),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct Asteroid {
+    pub size: AsteroidSize,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BossState {
+    /// still being put together, and staying put
+    Building,
+    /// complete and chasing the player
+    Active,
+}
+
+/// The Sinistar. Its `Health` is spread over its pieces, and each piece only shows up once there's
+/// enough health to cover it. Workers add pieces by bringing it crystals.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct Boss {
+    pub state: BossState,
+    pub pieces: u16,
+}
+
+impl Boss {
+    pub const PIECE_HEALTH: u16 = 3;
+
+    pub fn max_health(&self) -> u16 {
+        self.pieces * Self::PIECE_HEALTH
+    }
+
+    pub fn pieces_built(&self, health: &Health) -> usize {
+        ((health.0 + Self::PIECE_HEALTH - 1) / Self::PIECE_HEALTH) as usize
+    }
+
+    pub fn add_piece(&self, health: &mut Health) {
+        health.0 = (health.0 + Self::PIECE_HEALTH).min(self.max_health());
+    }
+
+    pub fn is_complete(&self, health: &Health) -> bool {
+        health.0 >= self.max_health()
+    }
+}
+
+/// Picked up by workers to build the Sinistar, and by the player to make sinibombs.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct Crystal;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WorkerState {
+    /// looking for a crystal to pick up
+    Seeking,
+    /// carrying a crystal to the Sinistar
+    Delivering,
+}
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct Worker {
+    pub state: WorkerState,
+}
+
+/// Enemy ship that keeps its distance from the player and shoots at it.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct Warrior {
+    /// time left until it can fire again
+    pub reload: Duration,
+}
+
+/// Homes in on the Sinistar, or the nearest enemy when there isn't one.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct Sinibomb {
+    /// time left until it fizzles out
+    pub fuse: Duration,
+}
+
+/// A projectile that also damages everything it can hit within `radius` of where it lands.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct AreaDamage {
+    pub radius: f32,
+    pub damage: u16,
+}
+
+/// Sprays particles while `intensity` is above 0, like the player's engine.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct Emitter {
+    pub style: ParticleStyle,
+    /// Particles per second at full intensity.
+    pub rate: f32,
+    /// From 0 for off to 1 for all out.
+    pub intensity: f32,
+    /// Where particles come out and which way they go, relative to the entity.
+    pub offset: Vector2<f32>,
+    pub direction: f32,
+    /// Radians either side of `direction` that particles can go.
+    pub spread: f32,
+    pub speed: f32,
+    /// Part of a particle left over from the last step, so low rates still add up.
+    pub owed: f32,
+}
+
+impl Emitter {
+    /// Out of the back of a ship, for as long as it's thrusting.
+    pub fn exhaust() -> Self {
+        Emitter {
+            style: ParticleStyle::EXHAUST,
+            rate: 60.,
+            intensity: 0.,
+            offset: Vector2::new(0., -0.5),
+            direction: -std::f32::consts::FRAC_PI_2,
+            spread: 0.3,
+            speed: 6.,
+            owed: 0.,
+        }
+    }
+}
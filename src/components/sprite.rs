@@ -1,5 +1,23 @@
-#[derive(Copy, Clone, Debug)]
+use na::Vector2;
+use serde::{Deserialize, Serialize};
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub struct Sprite {
     pub index: usize,
     pub color: [f32; 3],
 }
+
+/// One cell of a `CompositeSprite`, placed relative to the entity's transform.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct SpritePart {
+    pub index: usize,
+    pub color: [f32; 3],
+    pub offset: Vector2<f32>,
+    pub visible: bool,
+}
+
+/// For things too big for one spritesheet cell, like the Sinistar.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CompositeSprite {
+    pub parts: Vec<SpritePart>,
+}
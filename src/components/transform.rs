@@ -1,6 +1,7 @@
 use na::{Complex, Isometry2, Isometry3, Matrix4, UnitQuaternion, Vector2, Vector3};
+use serde::{Deserialize, Serialize};
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub struct Transform {
     pub isometry: Isometry3<f32>,
     pub scale: Vector3<f32>,
@@ -1 +1,9 @@
+use std::time::Duration;
+
 pub const SPRITES_PER_HALF_SCREEN: f32 = 15.;
+
+/// Length of one simulation step. Gameplay and physics always advance by this much, no matter how
+/// fast frames are rendered.
+pub const FIXED_TIMESTEP: Duration = Duration::from_nanos(16_666_667);
+/// Longest frame we try to catch up on, so a stall doesn't turn into hundreds of steps.
+pub const MAX_FRAME_TIME: Duration = Duration::from_millis(250);
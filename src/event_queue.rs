@@ -62,6 +62,19 @@ pub struct EventQueueWrapper<'a, T: 'a> {
     r: RefMut<'a, Vec<T>>,
 }
 
+impl<'a, T> SharedEventQueueWrapper<'a, T> {
+    /// Looks at the queued events without removing them, so several systems can react to them.
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.r.iter()
+    }
+}
+
+impl<'a, T> EventQueueWrapper<'a, T> {
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.r.iter()
+    }
+}
+
 impl<'a, 'b: 'a, T> Drain for EventQueueWrapper<'b, T> {
     type Item = T;
 
@@ -114,4 +127,12 @@ mod test {
         let q = e.0.lock().unwrap();
         assert!(q.is_empty());
     }
+
+    #[test]
+    fn iter_keeps_events() {
+        let e: SharedEventQueue<MyEventType> = SharedEventQueue::default();
+        e.push(MyEventType::SomeEvent);
+        assert_eq!(e.get_mut().iter().count(), 1);
+        assert_eq!(e.get_mut().iter().count(), 1);
+    }
 }
@@ -0,0 +1,34 @@
+use legion::Entity;
+use na::{Isometry2, Vector2};
+
+use crate::components::EntityTag;
+
+/// Things that happened during a simulation step that other systems may want to react to. The
+/// queue is cleared by `Game` at the end of every step, so systems reading events need to run
+/// after the systems producing them.
+#[derive(Debug, Copy, Clone)]
+pub enum GameEvent {
+    /// A projectile touched something its `can_hit` mask allows.
+    Hit { projectile: Entity, target: Entity },
+    Damaged {
+        entity: Entity,
+        amount: u16,
+        remaining: u16,
+    },
+    /// An entity ran out of health. It is despawned at the end of the step, but its last position
+    /// and velocity are kept here.
+    Destroyed {
+        entity: Entity,
+        tag: EntityTag,
+        position: Isometry2<f32>,
+        linvel: Vector2<f32>,
+    },
+    /// The player flew into a crystal.
+    CrystalCollected { player: Entity, crystal: Entity },
+    /// A worker snatched a crystal from the player.
+    CrystalStolen { player: Entity, worker: Entity },
+    /// Every enemy in the wave is gone. The next one comes in after a short break.
+    WaveCompleted { wave: u32 },
+    /// A clip that plays once got to its end.
+    AnimationFinished { entity: Entity },
+}
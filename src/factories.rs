@@ -1,4 +1,5 @@
 use std::sync::Arc;
+use std::time::Duration;
 
 use legion::storage::IntoComponentSource;
 use legion::systems::{CommandBuffer, WorldWritable};
@@ -23,6 +24,19 @@ pub trait EntityBuilder: std::fmt::Debug {
         // update the entities with their physics components on the ECS side
         self.update_world(world, &entities, &handles);
     }
+    /// Like `create`, but for systems, which can only add entities through a command buffer.
+    fn create_deferred(&self, cmd: &mut CommandBuffer, physics: &mut Physics) -> Vec<Entity>
+    where
+        Self::Components: 'static,
+    {
+        let entities = cmd.extend(self.components()).to_vec();
+        let handles = self.create_physics(physics, &entities);
+        for (e, h) in entities.iter().zip(handles.iter()) {
+            cmd.add_component(*e, *h);
+        }
+        debug!("Queued entity {:?} with components: {:?}", entities, self);
+        entities
+    }
     fn create_entities(&self, world: &mut World) -> Vec<Entity> {
         let v = world.extend(self.components()).to_vec();
         info!("Created entity {:?} with components: {:?}", v, self);
@@ -40,23 +54,42 @@ pub trait EntityBuilder: std::fmt::Debug {
 
 #[derive(Debug, Default)]
 pub struct AsteroidBuilder {
-    positions: Vec<Transform>,
+    asteroids: Vec<(Transform, AsteroidSize, Vector2<f32>)>,
 }
 
 impl AsteroidBuilder {
-    pub fn add_asteroid<T: Into<Transform>>(mut self, t: T) -> Self {
-        self.positions.push(t.into());
+    pub fn add_asteroid<T: Into<Transform>>(self, t: T) -> Self {
+        self.add_moving_asteroid(t, AsteroidSize::Large, Vector2::zeros())
+    }
+
+    pub fn add_planetoid<T: Into<Transform>>(self, t: T) -> Self {
+        self.add_moving_asteroid(t, AsteroidSize::Planetoid, Vector2::zeros())
+    }
+
+    pub fn add_moving_asteroid<T: Into<Transform>>(
+        mut self,
+        t: T,
+        size: AsteroidSize,
+        linvel: Vector2<f32>,
+    ) -> Self {
+        let scale = size.scale();
+        let t = t.into().with_scale(Vector3::new(scale, scale, 1.));
+        self.asteroids.push((t, size, linvel));
         self
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.asteroids.is_empty()
+    }
 }
 
 impl EntityBuilder for AsteroidBuilder {
-    type Components = Vec<(Transform, Sprite, EntityTag, Health)>;
+    type Components = Vec<(Transform, Sprite, EntityTag, Health, Asteroid)>;
 
     fn components(&self) -> Self::Components {
-        self.positions
+        self.asteroids
             .iter()
-            .map(|p| {
+            .map(|(p, size, _)| {
                 (
                     *p,
                     Sprite {
@@ -64,7 +97,8 @@ impl EntityBuilder for AsteroidBuilder {
                         color: [1., 1., 1.],
                     },
                     EntityTag::ASTEROID,
-                    Health(4),
+                    Health(size.health()),
+                    Asteroid { size: *size },
                 )
             })
             .collect::<Self::Components>()
@@ -73,29 +107,67 @@ impl EntityBuilder for AsteroidBuilder {
     fn create_physics(&self, physics: &mut Physics, entities: &[Entity]) -> Vec<RigidBodyHandle> {
         entities
             .iter()
-            .zip(self.positions.iter())
-            .map(|(e, t)| {
-                let rbb = RigidBodyBuilder::new_dynamic().position(t.as_2d());
-                let collider = ColliderBuilder::ball(0.2).density(20.0);
+            .zip(self.asteroids.iter())
+            .map(|(e, (t, size, linvel))| {
+                let rbb = RigidBodyBuilder::new_dynamic()
+                    .position(t.as_2d())
+                    .linvel(linvel.x, linvel.y);
+                let collider = ColliderBuilder::ball(size.radius()).density(20.0);
                 physics.create(*e, rbb, vec![collider])
             })
             .collect()
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug)]
 pub struct BulletBuilder {
     positions: Vec<(Transform, Vector3<f32>)>,
+    owner: EntityTag,
+    can_hit: EntityTag,
+    sprite: Sprite,
 }
 
 impl BulletBuilder {
+    /// A bullet fired from `t` in the direction it's facing. Bullets belong to the player unless
+    /// `fired_by` says otherwise.
     pub fn starting_from(mut t: Transform, speed: f32) -> Self {
         let mut bullet_vec = t.isometry.rotation * Vector3::y() * 1.25;
         t.isometry.translation.vector += bullet_vec;
         bullet_vec.set_magnitude(speed);
         BulletBuilder {
             positions: vec![(t, bullet_vec)],
+            owner: EntityTag::PLAYER,
+            can_hit: EntityTag::ENEMY_OR_ASTEROID | EntityTag::BOSS,
+            sprite: Sprite {
+                index: 2,
+                color: [1., 0., 0.],
+            },
+        }
+    }
+
+    /// Sets who fired the bullet, along with that side's usual targets and color. Enemies and the
+    /// boss shoot at the player; anything else shoots like the player does. `can_hit` and
+    /// `with_sprite` can change those afterwards.
+    pub fn fired_by(mut self, owner: EntityTag) -> Self {
+        self.owner = owner;
+        if owner.intersects(EntityTag::ENEMY | EntityTag::BOSS) {
+            self.can_hit = EntityTag::PLAYER_OR_ASTEROID;
+            self.sprite.color = [1., 0.8, 0.];
+        } else {
+            self.can_hit = EntityTag::ENEMY_OR_ASTEROID | EntityTag::BOSS;
+            self.sprite.color = [1., 0., 0.];
         }
+        self
+    }
+
+    pub fn can_hit(mut self, mask: EntityTag) -> Self {
+        self.can_hit = mask;
+        self
+    }
+
+    pub fn with_sprite(mut self, sprite: Sprite) -> Self {
+        self.sprite = sprite;
+        self
     }
 }
 
@@ -108,13 +180,11 @@ impl EntityBuilder for BulletBuilder {
             .map(|(p, _)| {
                 (
                     *p,
-                    Sprite {
-                        index: 2,
-                        color: [1., 0., 0.],
-                    },
+                    self.sprite,
                     EntityTag::PROJECTILE,
                     Projectile {
-                        can_hit: EntityTag::ENEMY_OR_ASTEROID,
+                        owner: self.owner,
+                        can_hit: self.can_hit,
                         damage: 1,
                     },
                     Cull,
@@ -139,6 +209,82 @@ impl EntityBuilder for BulletBuilder {
     }
 }
 
+const SINIBOMB_SPEED: f32 = 4.0;
+const SINIBOMB_FUSE: Duration = Duration::from_secs(8);
+
+#[derive(Debug)]
+pub struct SinibombBuilder {
+    positions: Vec<(Transform, Vector3<f32>)>,
+}
+
+impl SinibombBuilder {
+    /// A sinibomb launched from `t`, drifting out of the front of the ship until it picks a
+    /// target.
+    pub fn starting_from(mut t: Transform) -> Self {
+        let mut velocity = t.isometry.rotation * Vector3::y() * 1.25;
+        t.isometry.translation.vector += velocity;
+        velocity.set_magnitude(SINIBOMB_SPEED);
+        SinibombBuilder {
+            positions: vec![(t, velocity)],
+        }
+    }
+}
+
+impl EntityBuilder for SinibombBuilder {
+    type Components = Vec<(
+        Transform,
+        Sprite,
+        EntityTag,
+        Projectile,
+        AreaDamage,
+        Sinibomb,
+    )>;
+
+    fn components(&self) -> Self::Components {
+        self.positions
+            .iter()
+            .map(|(p, _)| {
+                (
+                    *p,
+                    Sprite {
+                        index: 10,
+                        color: [1., 1., 0.4],
+                    },
+                    EntityTag::PROJECTILE,
+                    // anything in the way sets it off, not just the Sinistar
+                    Projectile {
+                        owner: EntityTag::PLAYER,
+                        can_hit: EntityTag::ENEMY_OR_ASTEROID | EntityTag::BOSS,
+                        damage: 3,
+                    },
+                    AreaDamage {
+                        radius: 3.0,
+                        damage: 2,
+                    },
+                    Sinibomb {
+                        fuse: SINIBOMB_FUSE,
+                    },
+                )
+            })
+            .collect::<Self::Components>()
+    }
+
+    fn create_physics(&self, physics: &mut Physics, entities: &[Entity]) -> Vec<RigidBodyHandle> {
+        entities
+            .iter()
+            .zip(self.positions.iter())
+            .map(|(e, (t, velocity))| {
+                let rbb = RigidBodyBuilder::new_dynamic()
+                    .position(t.as_2d())
+                    .linvel(velocity.x, velocity.y)
+                    .can_sleep(false);
+                let collider = ColliderBuilder::ball(0.4).sensor(true);
+                physics.create(*e, rbb, vec![collider])
+            })
+            .collect()
+    }
+}
+
 #[derive(Debug)]
 pub struct PlayerBuilder {
     positions: Vec<Transform>,
@@ -151,7 +297,15 @@ impl PlayerBuilder {
 }
 
 impl EntityBuilder for PlayerBuilder {
-    type Components = Vec<(Transform, Sprite, EntityTag, Player, Health)>;
+    type Components = Vec<(
+        Transform,
+        Sprite,
+        EntityTag,
+        Player,
+        Health,
+        Inventory,
+        Emitter,
+    )>;
 
     fn components(&self) -> Self::Components {
         self.positions
@@ -163,9 +317,11 @@ impl EntityBuilder for PlayerBuilder {
                         index: 1,
                         color: [1., 1., 1.],
                     },
-                    EntityTag::PROJECTILE,
+                    EntityTag::PLAYER,
                     Player,
-                    Health(30),
+                    Health(Player::HEALTH),
+                    Inventory::default(),
+                    Emitter::exhaust(),
                 )
             })
             .collect::<Self::Components>()
@@ -188,3 +344,289 @@ impl EntityBuilder for PlayerBuilder {
             .collect()
     }
 }
+
+// sprite index and offset of each of the Sinistar's pieces, in the order they get built
+const BOSS_PLATE: usize = 4;
+const BOSS_EYE: usize = 5;
+const BOSS_MOUTH: usize = 6;
+const BOSS_LAYOUT: [(usize, [f32; 2]); 9] = [
+    (BOSS_PLATE, [0., 0.]),
+    (BOSS_PLATE, [-1., 1.]),
+    (BOSS_PLATE, [0., 1.]),
+    (BOSS_PLATE, [1., 1.]),
+    (BOSS_PLATE, [-1., -1.]),
+    (BOSS_PLATE, [1., -1.]),
+    (BOSS_EYE, [-1., 0.]),
+    (BOSS_EYE, [1., 0.]),
+    (BOSS_MOUTH, [0., -1.]),
+];
+
+#[derive(Debug)]
+pub struct BossBuilder {
+    positions: Vec<Transform>,
+}
+
+impl BossBuilder {
+    pub fn starting_from(t: Transform) -> Self {
+        BossBuilder { positions: vec![t] }
+    }
+
+    /// The collider for one of the boss's pieces. Pieces only get one once they're built, so the
+    /// gaps left by the rest don't get in the way of anything.
+    pub fn piece_collider(piece: usize) -> ColliderBuilder {
+        let (_, offset) = BOSS_LAYOUT[piece];
+        ColliderBuilder::cuboid(0.5, 0.5)
+            .translation(offset[0], offset[1])
+            .density(50.0)
+    }
+}
+
+impl EntityBuilder for BossBuilder {
+    type Components = Vec<(Transform, CompositeSprite, EntityTag, Health, Boss)>;
+
+    fn components(&self) -> Self::Components {
+        self.positions
+            .iter()
+            .map(|p| {
+                let parts = BOSS_LAYOUT
+                    .iter()
+                    .map(|(index, offset)| SpritePart {
+                        index: *index,
+                        color: [0.8, 0.1, 0.1],
+                        offset: Vector2::new(offset[0], offset[1]),
+                        visible: false,
+                    })
+                    .collect();
+                (
+                    *p,
+                    CompositeSprite { parts },
+                    EntityTag::BOSS,
+                    // starts out with a single piece
+                    Health(Boss::PIECE_HEALTH),
+                    Boss {
+                        state: BossState::Building,
+                        pieces: BOSS_LAYOUT.len() as u16,
+                    },
+                )
+            })
+            .collect::<Self::Components>()
+    }
+
+    fn create_physics(&self, physics: &mut Physics, entities: &[Entity]) -> Vec<RigidBodyHandle> {
+        entities
+            .iter()
+            .zip(self.positions.iter())
+            .map(|(e, t)| {
+                let rbb = RigidBodyBuilder::new_dynamic()
+                    .position(t.as_2d())
+                    .can_sleep(false);
+                // the first piece, and `boss_construction` adds the rest
+                physics.create(*e, rbb, vec![Self::piece_collider(0)])
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct WorkerBuilder {
+    positions: Vec<Transform>,
+}
+
+impl WorkerBuilder {
+    pub fn add_worker<T: Into<Transform>>(mut self, t: T) -> Self {
+        self.positions.push(t.into());
+        self
+    }
+}
+
+impl EntityBuilder for WorkerBuilder {
+    type Components = Vec<(Transform, Sprite, EntityTag, Health, Worker)>;
+
+    fn components(&self) -> Self::Components {
+        self.positions
+            .iter()
+            .map(|p| {
+                (
+                    *p,
+                    Sprite {
+                        index: 7,
+                        color: [0.3, 1., 0.3],
+                    },
+                    EntityTag::ENEMY,
+                    Health(2),
+                    Worker {
+                        state: WorkerState::Seeking,
+                    },
+                )
+            })
+            .collect::<Self::Components>()
+    }
+
+    fn create_physics(&self, physics: &mut Physics, entities: &[Entity]) -> Vec<RigidBodyHandle> {
+        entities
+            .iter()
+            .zip(self.positions.iter())
+            .map(|(e, t)| {
+                let rbb = RigidBodyBuilder::new_dynamic()
+                    .position(t.as_2d())
+                    .can_sleep(false);
+                let collider = ColliderBuilder::cuboid(0.3, 0.3);
+                physics.create(*e, rbb, vec![collider])
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct CrystalBuilder {
+    crystals: Vec<(Transform, Vector2<f32>)>,
+}
+
+impl CrystalBuilder {
+    pub fn add_crystal<T: Into<Transform>>(mut self, t: T, linvel: Vector2<f32>) -> Self {
+        self.crystals.push((t.into(), linvel));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.crystals.is_empty()
+    }
+}
+
+impl EntityBuilder for CrystalBuilder {
+    type Components = Vec<(Transform, Sprite, EntityTag, Crystal)>;
+
+    fn components(&self) -> Self::Components {
+        self.crystals
+            .iter()
+            .map(|(p, _)| {
+                (
+                    *p,
+                    Sprite {
+                        index: 8,
+                        color: [0.4, 0.9, 1.],
+                    },
+                    EntityTag::CRYSTAL,
+                    Crystal,
+                )
+            })
+            .collect::<Self::Components>()
+    }
+
+    fn create_physics(&self, physics: &mut Physics, entities: &[Entity]) -> Vec<RigidBodyHandle> {
+        entities
+            .iter()
+            .zip(self.crystals.iter())
+            .map(|(e, (t, linvel))| {
+                let rbb = RigidBodyBuilder::new_dynamic()
+                    .position(t.as_2d())
+                    .linvel(linvel.x, linvel.y)
+                    .can_sleep(false);
+                let collider = ColliderBuilder::ball(0.25).sensor(true);
+                physics.create(*e, rbb, vec![collider])
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct WarriorBuilder {
+    positions: Vec<Transform>,
+}
+
+impl WarriorBuilder {
+    pub fn add_warrior<T: Into<Transform>>(mut self, t: T) -> Self {
+        self.positions.push(t.into());
+        self
+    }
+}
+
+impl EntityBuilder for WarriorBuilder {
+    type Components = Vec<(Transform, Sprite, EntityTag, Health, Warrior)>;
+
+    fn components(&self) -> Self::Components {
+        self.positions
+            .iter()
+            .map(|p| {
+                (
+                    *p,
+                    Sprite {
+                        index: 9,
+                        color: [1., 0.5, 0.1],
+                    },
+                    EntityTag::ENEMY,
+                    Health(3),
+                    Warrior {
+                        reload: Duration::default(),
+                    },
+                )
+            })
+            .collect::<Self::Components>()
+    }
+
+    fn create_physics(&self, physics: &mut Physics, entities: &[Entity]) -> Vec<RigidBodyHandle> {
+        entities
+            .iter()
+            .zip(self.positions.iter())
+            .map(|(e, t)| {
+                let rbb = RigidBodyBuilder::new_dynamic()
+                    .position(t.as_2d())
+                    .can_sleep(false);
+                let collider = ColliderBuilder::cuboid(0.4, 0.4);
+                physics.create(*e, rbb, vec![collider])
+            })
+            .collect()
+    }
+}
+
+const EXPLOSION_FRAMES: [usize; 4] = [14, 15, 16, 17];
+const EXPLOSION_FRAME_TIME: Duration = Duration::from_millis(80);
+
+/// Explosions are only for show, so they have no rigid body, and they remove themselves once
+/// they've played.
+#[derive(Debug, Default)]
+pub struct ExplosionBuilder {
+    positions: Vec<Transform>,
+}
+
+impl ExplosionBuilder {
+    pub fn add_explosion<T: Into<Transform>>(mut self, t: T, scale: f32) -> Self {
+        self.positions
+            .push(t.into().with_scale(Vector3::new(scale, scale, 1.)));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+}
+
+impl EntityBuilder for ExplosionBuilder {
+    type Components = Vec<(Transform, Sprite, Animation, OneShot)>;
+
+    fn components(&self) -> Self::Components {
+        let clip = Clip::new(
+            EXPLOSION_FRAMES.to_vec(),
+            EXPLOSION_FRAME_TIME,
+            Playback::Once,
+        );
+        self.positions
+            .iter()
+            .map(|p| {
+                (
+                    *p,
+                    Sprite {
+                        index: EXPLOSION_FRAMES[0],
+                        color: [1., 0.7, 0.3],
+                    },
+                    Animation::new("explode", clip.clone()),
+                    OneShot,
+                )
+            })
+            .collect::<Self::Components>()
+    }
+
+    fn create_physics(&self, _physics: &mut Physics, _entities: &[Entity]) -> Vec<RigidBodyHandle> {
+        vec![]
+    }
+}
@@ -1,14 +1,59 @@
-use std::collections::{HashSet, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use serde::{Deserialize, Serialize};
 
 #[cfg(not(target_arch = "wasm32"))]
-use glfw::Key as GKey;
+use glfw::{
+    Action as GAction, GamepadAxis as GGamepadAxis, GamepadButton as GGamepadButton,
+    GamepadState as GGamepadState, Key as GKey,
+};
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::JsCast;
 #[cfg(target_arch = "wasm32")]
-use web_sys::{KeyboardEvent, MouseEvent};
+use web_sys::{Element, KeyboardEvent, MouseEvent, TouchEvent};
+
+/// The bindings the game starts with, unless it's given others.
+pub const DEFAULT_INPUT_MAP: &str = include_str!("../config/input.ron");
+/// How far a stick or trigger has to be pushed before it counts as a press.
+pub const AXIS_THRESHOLD: f32 = 0.5;
+/// Sticks never quite come back to the middle, so anything this close to it counts as the middle.
+pub const DEAD_ZONE: f32 = 0.15;
+// smaller changes than this aren't worth an event, which would end up in every replay
+const AXIS_EPSILON: f32 = 0.01;
+// touches that start this far out from the middle of the virtual stick, in stick radii, still grab
+// it, since it's hard to hit without looking
+const STICK_REACH: f32 = 1.5;
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct InputState {
     pressed_keys: HashSet<Key>,
     repeated_key: Option<Key>,
+    pressed_buttons: HashSet<MouseButton>,
+    /// Where the mouse was last seen, in pixels from the top left of the window.
+    cursor: Option<(i32, i32)>,
+    pressed_gamepad_buttons: HashSet<GamepadButton>,
+    gamepad_axes: HashMap<GamepadAxis, f32>,
+    // actions held down this step and the one before, for telling when they change
+    actions: HashSet<Action>,
+    previous_actions: HashSet<Action>,
+    // how hard each action is being pushed, for the ones that are
+    action_values: HashMap<Action, f32>,
+    touches: HashMap<i32, Touch>,
+    /// Set by the first touch, so that the touch controls only show up on touch screens.
+    uses_touch: bool,
+    // which touch is holding the virtual stick, and where it's pushed to
+    stick_touch: Option<i32>,
+    touch_stick: (f32, f32),
+    touch_buttons: HashSet<TouchInput>,
+    // whether this step's input asked for a game to start
+    start_requested: bool,
+}
+
+/// A finger on the screen, in pixels from the top left of the window.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Touch {
+    pub start: (i32, i32),
+    pub position: (i32, i32),
 }
 
 impl InputState {
@@ -26,9 +71,351 @@ impl InputState {
     pub fn is_pressed(&self, code: Key) -> bool {
         self.pressed_keys.contains(&code)
     }
+
+    pub fn press_button(&mut self, button: MouseButton) {
+        self.pressed_buttons.insert(button);
+    }
+    pub fn release_button(&mut self, button: MouseButton) {
+        self.pressed_buttons.remove(&button);
+    }
+    pub fn move_cursor(&mut self, x: i32, y: i32) {
+        self.cursor = Some((x, y));
+    }
+    /// Where the mouse is, in pixels from the top left of the window, once it's been moved.
+    pub fn cursor(&self) -> Option<(i32, i32)> {
+        self.cursor
+    }
+
+    pub fn press_gamepad_button(&mut self, button: GamepadButton) {
+        self.pressed_gamepad_buttons.insert(button);
+    }
+    pub fn release_gamepad_button(&mut self, button: GamepadButton) {
+        self.pressed_gamepad_buttons.remove(&button);
+    }
+    pub fn set_axis(&mut self, axis: GamepadAxis, value: f32) {
+        self.gamepad_axes.insert(axis, value);
+    }
+    /// Where a stick or trigger is, with the dead zone taken out. Sticks go from -1 to 1 and point
+    /// up and right for positive values, and triggers go from 0 to 1.
+    pub fn axis(&self, axis: GamepadAxis) -> f32 {
+        self.gamepad_axes.get(&axis).copied().unwrap_or_default()
+    }
+
+    pub fn touch_start(&mut self, id: i32, position: (i32, i32)) {
+        self.uses_touch = true;
+        self.touches.insert(
+            id,
+            Touch {
+                start: position,
+                position,
+            },
+        );
+    }
+    pub fn touch_move(&mut self, id: i32, position: (i32, i32)) {
+        if let Some(touch) = self.touches.get_mut(&id) {
+            touch.position = position;
+        }
+    }
+    pub fn touch_end(&mut self, id: i32) {
+        self.touches.remove(&id);
+    }
+    pub fn touches(&self) -> impl Iterator<Item = &Touch> {
+        self.touches.values()
+    }
+    pub fn uses_touch(&self) -> bool {
+        self.uses_touch
+    }
+    /// Where the virtual stick is pushed, with the dead zone taken out, in the same directions as
+    /// a gamepad's sticks.
+    pub fn touch_stick(&self) -> (f32, f32) {
+        self.touch_stick
+    }
+    pub fn is_touch_button_pressed(&self, button: TouchInput) -> bool {
+        self.touch_buttons.contains(&button)
+    }
+
+    pub fn set_start_requested(&mut self, requested: bool) {
+        self.start_requested = requested;
+    }
+    /// Whether a `StartGameEvent` came in this step.
+    pub fn start_requested(&self) -> bool {
+        self.start_requested
+    }
+
+    /// Works out what the touches are doing to the on-screen controls. Called once per step,
+    /// before `update_actions`.
+    pub fn update_touch_controls(&mut self, layout: &TouchLayout) {
+        if let Some(id) = self.stick_touch {
+            if !self.touches.contains_key(&id) {
+                self.stick_touch = None;
+            }
+        }
+        if self.stick_touch.is_none() {
+            // the lowest id if more than one could have it, so replays pick the same one
+            self.stick_touch = self
+                .touches
+                .iter()
+                .filter(|(_, touch)| layout.stick.reaches(touch.start, STICK_REACH))
+                .map(|(id, _)| *id)
+                .min();
+        }
+
+        self.touch_stick = match self.stick_touch.and_then(|id| self.touches.get(&id)) {
+            Some(touch) => {
+                let stick = layout.stick;
+                let x = (touch.position.0 as f32 - stick.x) / stick.radius;
+                // up the screen is positive, like on a gamepad
+                let y = (stick.y - touch.position.1 as f32) / stick.radius;
+                let length = (x * x + y * y).sqrt();
+                let scale = if length > 1. { 1. / length } else { 1. };
+                (apply_dead_zone(x * scale), apply_dead_zone(y * scale))
+            }
+            None => (0., 0.),
+        };
+
+        self.touch_buttons.clear();
+        let stick_touch = self.stick_touch;
+        for (id, touch) in &self.touches {
+            if Some(*id) == stick_touch {
+                continue;
+            }
+            if layout.fire.reaches(touch.position, 1.) {
+                self.touch_buttons.insert(TouchInput::Fire);
+            }
+            if layout.bomb.reaches(touch.position, 1.) {
+                self.touch_buttons.insert(TouchInput::Bomb);
+            }
+        }
+    }
+
+    pub fn is_binding_active(&self, binding: Binding) -> bool {
+        match binding {
+            Binding::Key(key) => self.is_pressed(key),
+            Binding::Mouse(button) => self.pressed_buttons.contains(&button),
+            Binding::GamepadButton(button) => self.pressed_gamepad_buttons.contains(&button),
+            Binding::Touch(button @ TouchInput::Fire)
+            | Binding::Touch(button @ TouchInput::Bomb) => self.touch_buttons.contains(&button),
+            // sticks and triggers
+            _ => self.binding_value(binding) > AXIS_THRESHOLD,
+        }
+    }
+
+    /// How far `binding` is pushed, from 0 to 1. Keys and buttons are all or nothing.
+    pub fn binding_value(&self, binding: Binding) -> f32 {
+        let (x, y) = self.touch_stick;
+        match binding {
+            Binding::GamepadAxis { axis, positive } => {
+                let value = self.axis(axis);
+                if positive {
+                    value.max(0.)
+                } else {
+                    (-value).max(0.)
+                }
+            }
+            Binding::Touch(TouchInput::StickLeft) => (-x).max(0.),
+            Binding::Touch(TouchInput::StickRight) => x.max(0.),
+            Binding::Touch(TouchInput::StickUp) => y.max(0.),
+            Binding::Touch(TouchInput::StickDown) => (-y).max(0.),
+            _ if self.is_binding_active(binding) => 1.,
+            _ => 0.,
+        }
+    }
+
+    /// Works out which actions are held down, from whatever's pressed. Called once per step,
+    /// after the step's input has been handled.
+    pub fn update_actions(&mut self, map: &InputMap) {
+        let mut actions = HashSet::new();
+        self.action_values.clear();
+        for (action, bindings) in &map.bindings {
+            if bindings.iter().any(|b| self.is_binding_active(*b)) {
+                actions.insert(*action);
+            }
+            let value = bindings
+                .iter()
+                .map(|b| self.binding_value(*b))
+                .fold(0., f32::max);
+            if value > 0. {
+                self.action_values.insert(*action, value);
+            }
+        }
+        self.previous_actions = std::mem::replace(&mut self.actions, actions);
+    }
+    /// How hard `action` is being pushed, from 0 to 1. Sticks can push an action a little without
+    /// pressing it.
+    pub fn action_value(&self, action: Action) -> f32 {
+        self.action_values.get(&action).copied().unwrap_or_default()
+    }
+    pub fn is_action_pressed(&self, action: Action) -> bool {
+        self.actions.contains(&action)
+    }
+    /// Whether `action` went down this step.
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.actions.contains(&action) && !self.previous_actions.contains(&action)
+    }
+    /// Whether `action` was let go of this step.
+    pub fn just_released(&self, action: Action) -> bool {
+        !self.actions.contains(&action) && self.previous_actions.contains(&action)
+    }
+}
+
+/// What the player can do, whichever keys or buttons they do it with.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum Action {
+    RotateLeft,
+    RotateRight,
+    Thrust,
+    Fire,
+    Bomb,
+    Pause,
+}
+
+/// A physical input that can trigger an action.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Binding {
+    Key(Key),
+    Mouse(MouseButton),
+    GamepadButton(GamepadButton),
+    /// A stick or trigger pushed past `AXIS_THRESHOLD` one way or the other.
+    GamepadAxis {
+        axis: GamepadAxis,
+        positive: bool,
+    },
+    /// The on-screen controls. The stick's directions work like a gamepad stick's.
+    Touch(TouchInput),
+}
+
+/// The parts of the on-screen controls for touch screens.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum TouchInput {
+    StickLeft,
+    StickRight,
+    StickUp,
+    StickDown,
+    Fire,
+    Bomb,
+}
+
+/// A round on-screen control, in pixels from the top left of the window.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TouchCircle {
+    pub x: f32,
+    pub y: f32,
+    pub radius: f32,
+}
+
+impl TouchCircle {
+    /// Whether `point` is within `reach` radii of the middle.
+    pub fn reaches(&self, (x, y): (i32, i32), reach: f32) -> bool {
+        let (dx, dy) = (x as f32 - self.x, y as f32 - self.y);
+        (dx * dx + dy * dy).sqrt() <= self.radius * reach
+    }
+}
+
+/// Where the on-screen controls go: the stick in the bottom left corner, and the buttons in the
+/// bottom right, where thumbs can reach them.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TouchLayout {
+    pub stick: TouchCircle,
+    pub fire: TouchCircle,
+    pub bomb: TouchCircle,
+}
+
+impl TouchLayout {
+    /// Sizes everything from the window's height, so the controls stay thumb-sized on wide
+    /// screens.
+    pub fn for_window(w: u32, h: u32) -> Self {
+        let (w, h) = (w as f32, h as f32);
+        let margin = h * 0.08;
+        let stick = h * 0.16;
+        let fire = h * 0.1;
+        let bomb = h * 0.07;
+        let fire_x = w - margin - fire;
+        let fire_y = h - margin - fire;
+        TouchLayout {
+            stick: TouchCircle {
+                x: margin + stick,
+                y: h - margin - stick,
+                radius: stick,
+            },
+            fire: TouchCircle {
+                x: fire_x,
+                y: fire_y,
+                radius: fire,
+            },
+            bomb: TouchCircle {
+                x: fire_x,
+                y: fire_y - fire - margin / 2. - bomb,
+                radius: bomb,
+            },
+        }
+    }
+}
+
+/// How the ship is steered.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum ControlScheme {
+    /// Turning left and right with `RotateLeft` and `RotateRight`.
+    Rotate,
+    /// Turning to face the mouse cursor. The rotate actions are ignored.
+    MouseAim,
+}
+
+impl Default for ControlScheme {
+    fn default() -> Self {
+        ControlScheme::Rotate
+    }
+}
+
+/// Which bindings trigger which actions. Any of an action's bindings will do.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InputMap {
+    #[serde(default)]
+    pub scheme: ControlScheme,
+    bindings: HashMap<Action, Vec<Binding>>,
+}
+
+impl InputMap {
+    /// Reads bindings from RON, in the same format as `config/input.ron`.
+    pub fn parse(source: &str) -> Result<InputMap, ron::Error> {
+        ron::de::from_str(source)
+    }
+
+    pub fn to_ron(&self) -> String {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .expect("input maps should always serialize")
+    }
+
+    pub fn bindings(&self, action: Action) -> &[Binding] {
+        self.bindings.get(&action).map_or(&[], |b| b.as_slice())
+    }
+
+    /// Adds another way to trigger `action`.
+    pub fn bind(&mut self, action: Action, binding: Binding) {
+        let bindings = self.bindings.entry(action).or_default();
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+    pub fn unbind(&mut self, action: Action, binding: Binding) {
+        if let Some(bindings) = self.bindings.get_mut(&action) {
+            bindings.retain(|b| *b != binding);
+        }
+    }
+
+    /// Replaces all of `action`'s bindings.
+    pub fn rebind(&mut self, action: Action, bindings: Vec<Binding>) {
+        self.bindings.insert(action, bindings);
+    }
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        InputMap::parse(DEFAULT_INPUT_MAP).expect("the default input map should be valid")
+    }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub enum MouseButton {
     Main,
     Aux,
@@ -37,13 +424,197 @@ pub enum MouseButton {
     Fifth,
 }
 
-#[derive(Debug, PartialEq)]
+/// Gamepad buttons, named by where they are rather than what's printed on them.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum GamepadButton {
+    South,
+    East,
+    West,
+    North,
+    LeftBumper,
+    RightBumper,
+    Back,
+    Start,
+    Guide,
+    LeftStick,
+    RightStick,
+    DPadUp,
+    DPadRight,
+    DPadDown,
+    DPadLeft,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum GamepadAxis {
+    LeftX,
+    LeftY,
+    RightX,
+    RightY,
+    LeftTrigger,
+    RightTrigger,
+}
+
+impl GamepadButton {
+    pub const ALL: [GamepadButton; 15] = [
+        GamepadButton::South,
+        GamepadButton::East,
+        GamepadButton::West,
+        GamepadButton::North,
+        GamepadButton::LeftBumper,
+        GamepadButton::RightBumper,
+        GamepadButton::Back,
+        GamepadButton::Start,
+        GamepadButton::Guide,
+        GamepadButton::LeftStick,
+        GamepadButton::RightStick,
+        GamepadButton::DPadUp,
+        GamepadButton::DPadRight,
+        GamepadButton::DPadDown,
+        GamepadButton::DPadLeft,
+    ];
+}
+
+impl GamepadAxis {
+    pub const ALL: [GamepadAxis; 6] = [
+        GamepadAxis::LeftX,
+        GamepadAxis::LeftY,
+        GamepadAxis::RightX,
+        GamepadAxis::RightY,
+        GamepadAxis::LeftTrigger,
+        GamepadAxis::RightTrigger,
+    ];
+}
+
+/// Takes the dead zone out of a stick or trigger, stretching what's left back out to the full
+/// range.
+pub fn apply_dead_zone(value: f32) -> f32 {
+    if value.abs() < DEAD_ZONE {
+        0.
+    } else {
+        value.signum() * (value.abs() - DEAD_ZONE) / (1. - DEAD_ZONE)
+    }
+}
+
+/// Everything a gamepad is doing at once, indexed in the same order as `GamepadAxis::ALL` and
+/// `GamepadButton::ALL`. Axes follow the same conventions as `InputState::axis`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GamepadState {
+    pub axes: [f32; 6],
+    pub buttons: [bool; 15],
+}
+
+impl GamepadState {
+    /// Reads the browser Gamepad API's "standard" layout, where `axes` are the two sticks and the
+    /// triggers are analog `buttons`.
+    pub fn from_standard_mapping(axes: &[f32], buttons: &[f32]) -> Self {
+        let axis = |i: usize| axes.get(i).copied().unwrap_or_default();
+        let button = |i: usize| buttons.get(i).copied().unwrap_or_default();
+        GamepadState {
+            // the Gamepad API has up as -1
+            axes: [axis(0), -axis(1), axis(2), -axis(3), button(6), button(7)],
+            buttons: [
+                button(0) > 0.5,
+                button(1) > 0.5,
+                button(2) > 0.5,
+                button(3) > 0.5,
+                button(4) > 0.5,
+                button(5) > 0.5,
+                button(8) > 0.5,
+                button(9) > 0.5,
+                button(16) > 0.5,
+                button(10) > 0.5,
+                button(11) > 0.5,
+                button(12) > 0.5,
+                button(15) > 0.5,
+                button(13) > 0.5,
+                button(14) > 0.5,
+            ],
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl From<&GGamepadState> for GamepadState {
+    fn from(s: &GGamepadState) -> Self {
+        let button = |b| s.get_button_state(b) == GAction::Press;
+        // GLFW has up as -1, and triggers resting at -1
+        let trigger = |a| (s.get_axis(a) + 1.) / 2.;
+        GamepadState {
+            axes: [
+                s.get_axis(GGamepadAxis::AxisLeftX),
+                -s.get_axis(GGamepadAxis::AxisLeftY),
+                s.get_axis(GGamepadAxis::AxisRightX),
+                -s.get_axis(GGamepadAxis::AxisRightY),
+                trigger(GGamepadAxis::AxisLeftTrigger),
+                trigger(GGamepadAxis::AxisRightTrigger),
+            ],
+            buttons: [
+                button(GGamepadButton::ButtonA),
+                button(GGamepadButton::ButtonB),
+                button(GGamepadButton::ButtonX),
+                button(GGamepadButton::ButtonY),
+                button(GGamepadButton::ButtonLeftBumper),
+                button(GGamepadButton::ButtonRightBumper),
+                button(GGamepadButton::ButtonBack),
+                button(GGamepadButton::ButtonStart),
+                button(GGamepadButton::ButtonGuide),
+                button(GGamepadButton::ButtonLeftThumb),
+                button(GGamepadButton::ButtonRightThumb),
+                button(GGamepadButton::ButtonDpadUp),
+                button(GGamepadButton::ButtonDpadRight),
+                button(GGamepadButton::ButtonDpadDown),
+                button(GGamepadButton::ButtonDpadLeft),
+            ],
+        }
+    }
+}
+
+/// Turns a gamepad that's polled for its state into events, for whatever changed since it was
+/// last polled.
+#[derive(Debug, Default)]
+pub struct Gamepad {
+    last: GamepadState,
+}
+
+impl Gamepad {
+    /// The events that take the gamepad from its last state to `state`. A disconnected gamepad
+    /// should be passed `GamepadState::default()`, so nothing's left held down.
+    pub fn update(&mut self, state: GamepadState) -> Vec<InputEvent> {
+        let mut events = vec![];
+        for (i, axis) in GamepadAxis::ALL.iter().enumerate() {
+            let value = apply_dead_zone(state.axes[i]);
+            let last = self.last.axes[i];
+            // always let it settle back to exactly 0, however small the last step was
+            if (value - last).abs() > AXIS_EPSILON || (value == 0. && last != 0.) {
+                self.last.axes[i] = value;
+                events.push(InputEvent::GamepadAxisEvent { axis: *axis, value });
+            }
+        }
+        for (i, button) in GamepadButton::ALL.iter().enumerate() {
+            let pressed = state.buttons[i];
+            if pressed != self.last.buttons[i] {
+                self.last.buttons[i] = pressed;
+                events.push(InputEvent::GamepadButtonEvent {
+                    button: *button,
+                    state: if pressed {
+                        KeyState::Pressed
+                    } else {
+                        KeyState::Released
+                    },
+                });
+            }
+        }
+        events
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum KeyState {
     Pressed,
     Released,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum InputEvent {
     KeyboardEvent {
         code: Key,
@@ -56,20 +627,166 @@ pub enum InputEvent {
         x: i32,
         y: i32,
     },
+    MouseMoveEvent {
+        x: i32,
+        y: i32,
+    },
+    GamepadButtonEvent {
+        button: GamepadButton,
+        state: KeyState,
+    },
+    GamepadAxisEvent {
+        axis: GamepadAxis,
+        value: f32,
+    },
+    TouchEvent {
+        id: i32,
+        phase: TouchPhase,
+        x: i32,
+        y: i32,
+    },
+    /// Starts a game, like pressing fire on the attract screen. This comes from `Game::start`
+    /// rather than a device, and goes through the input queue so that replays see it.
+    StartGameEvent,
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
-pub enum Key {
-    W,
-    A,
-    S,
-    D,
-    Left,
-    Right,
-    Up,
-    Down,
-    Space,
-    Unmapped,
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TouchPhase {
+    Started,
+    Moved,
+    /// Lifted off the screen, or cancelled by the browser.
+    Ended,
+}
+
+/// Declares `Key`, along with how browser key codes and GLFW keys map onto it. Browser codes name
+/// the physical key, whatever the keyboard layout.
+macro_rules! keys {
+    ($($key:ident => $code:literal, $glfw:ident;)*) => {
+        #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+        pub enum Key {
+            $($key,)*
+            Unmapped,
+        }
+
+        impl From<&str> for Key {
+            fn from(s: &str) -> Key {
+                match s {
+                    $($code => Key::$key,)*
+                    _ => Key::Unmapped,
+                }
+            }
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        impl From<GKey> for Key {
+            fn from(k: GKey) -> Key {
+                match k {
+                    $(GKey::$glfw => Key::$key,)*
+                    _ => Key::Unmapped,
+                }
+            }
+        }
+    };
+}
+
+keys! {
+    A => "KeyA", A;
+    B => "KeyB", B;
+    C => "KeyC", C;
+    D => "KeyD", D;
+    E => "KeyE", E;
+    F => "KeyF", F;
+    G => "KeyG", G;
+    H => "KeyH", H;
+    I => "KeyI", I;
+    J => "KeyJ", J;
+    K => "KeyK", K;
+    L => "KeyL", L;
+    M => "KeyM", M;
+    N => "KeyN", N;
+    O => "KeyO", O;
+    P => "KeyP", P;
+    Q => "KeyQ", Q;
+    R => "KeyR", R;
+    S => "KeyS", S;
+    T => "KeyT", T;
+    U => "KeyU", U;
+    V => "KeyV", V;
+    W => "KeyW", W;
+    X => "KeyX", X;
+    Y => "KeyY", Y;
+    Z => "KeyZ", Z;
+    Num0 => "Digit0", Num0;
+    Num1 => "Digit1", Num1;
+    Num2 => "Digit2", Num2;
+    Num3 => "Digit3", Num3;
+    Num4 => "Digit4", Num4;
+    Num5 => "Digit5", Num5;
+    Num6 => "Digit6", Num6;
+    Num7 => "Digit7", Num7;
+    Num8 => "Digit8", Num8;
+    Num9 => "Digit9", Num9;
+    F1 => "F1", F1;
+    F2 => "F2", F2;
+    F3 => "F3", F3;
+    F4 => "F4", F4;
+    F5 => "F5", F5;
+    F6 => "F6", F6;
+    F7 => "F7", F7;
+    F8 => "F8", F8;
+    F9 => "F9", F9;
+    F10 => "F10", F10;
+    F11 => "F11", F11;
+    F12 => "F12", F12;
+    Left => "ArrowLeft", Left;
+    Right => "ArrowRight", Right;
+    Up => "ArrowUp", Up;
+    Down => "ArrowDown", Down;
+    Space => "Space", Space;
+    Enter => "Enter", Enter;
+    Escape => "Escape", Escape;
+    Tab => "Tab", Tab;
+    Backspace => "Backspace", Backspace;
+    Insert => "Insert", Insert;
+    Delete => "Delete", Delete;
+    Home => "Home", Home;
+    End => "End", End;
+    PageUp => "PageUp", PageUp;
+    PageDown => "PageDown", PageDown;
+    CapsLock => "CapsLock", CapsLock;
+    LeftShift => "ShiftLeft", LeftShift;
+    RightShift => "ShiftRight", RightShift;
+    LeftControl => "ControlLeft", LeftControl;
+    RightControl => "ControlRight", RightControl;
+    LeftAlt => "AltLeft", LeftAlt;
+    RightAlt => "AltRight", RightAlt;
+    Minus => "Minus", Minus;
+    Equal => "Equal", Equal;
+    LeftBracket => "BracketLeft", LeftBracket;
+    RightBracket => "BracketRight", RightBracket;
+    Backslash => "Backslash", Backslash;
+    Semicolon => "Semicolon", Semicolon;
+    Apostrophe => "Quote", Apostrophe;
+    Comma => "Comma", Comma;
+    Period => "Period", Period;
+    Slash => "Slash", Slash;
+    GraveAccent => "Backquote", GraveAccent;
+    Keypad0 => "Numpad0", Kp0;
+    Keypad1 => "Numpad1", Kp1;
+    Keypad2 => "Numpad2", Kp2;
+    Keypad3 => "Numpad3", Kp3;
+    Keypad4 => "Numpad4", Kp4;
+    Keypad5 => "Numpad5", Kp5;
+    Keypad6 => "Numpad6", Kp6;
+    Keypad7 => "Numpad7", Kp7;
+    Keypad8 => "Numpad8", Kp8;
+    Keypad9 => "Numpad9", Kp9;
+    KeypadAdd => "NumpadAdd", KpAdd;
+    KeypadSubtract => "NumpadSubtract", KpSubtract;
+    KeypadMultiply => "NumpadMultiply", KpMultiply;
+    KeypadDivide => "NumpadDivide", KpDivide;
+    KeypadDecimal => "NumpadDecimal", KpDecimal;
+    KeypadEnter => "NumpadEnter", KpEnter;
 }
 
 #[cfg(target_arch = "wasm32")]
@@ -85,8 +802,9 @@ impl From<MouseEvent> for InputEvent {
                 _ => unreachable!("What is this mouse button?"),
             },
             state: KeyState::Pressed,
-            x: e.client_x(),
-            y: e.client_y(),
+            // relative to the canvas, rather than the page
+            x: e.offset_x(),
+            y: e.offset_y(),
         }
     }
 }
@@ -102,37 +820,120 @@ impl From<KeyboardEvent> for InputEvent {
     }
 }
 
-impl From<&str> for Key {
-    fn from(s: &str) -> Key {
-        match s {
-            "KeyW" => Key::W,
-            "KeyA" => Key::A,
-            "KeyS" => Key::S,
-            "KeyD" => Key::D,
-            "ArrowLeft" => Key::Left,
-            "ArrowRight" => Key::Right,
-            "ArrowUp" => Key::Up,
-            "ArrowDown" => Key::Down,
-            "Space" => Key::Space,
-            _ => Key::Unmapped,
-        }
-    }
+/// The events for every touch that changed in `e`, relative to the element it happened on.
+#[cfg(target_arch = "wasm32")]
+pub fn touch_events(e: &TouchEvent, phase: TouchPhase) -> Vec<InputEvent> {
+    let (left, top) = e
+        .target()
+        .and_then(|target| target.dyn_into::<Element>().ok())
+        .map(|element| {
+            let rect = element.get_bounding_client_rect();
+            (rect.left() as i32, rect.top() as i32)
+        })
+        .unwrap_or_default();
+    let touches = e.changed_touches();
+    (0..touches.length())
+        .filter_map(|i| touches.get(i))
+        .map(|touch| InputEvent::TouchEvent {
+            id: touch.identifier(),
+            phase,
+            x: touch.client_x() - left,
+            y: touch.client_y() - top,
+        })
+        .collect()
 }
 
-#[cfg(not(target_arch = "wasm32"))]
-impl From<GKey> for Key {
-    fn from(k: glfw::Key) -> Key {
-        match k {
-            GKey::W => Key::W,
-            GKey::A => Key::A,
-            GKey::S => Key::S,
-            GKey::D => Key::D,
-            GKey::Up => Key::Up,
-            GKey::Left => Key::Left,
-            GKey::Right => Key::Right,
-            GKey::Down => Key::Down,
-            GKey::Space => Key::Space,
-            _ => Key::Unmapped,
-        }
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn actions_have_edges() {
+        let map = InputMap::default();
+        let mut state = InputState::default();
+
+        state.press_key(Key::Space, false);
+        state.update_actions(&map);
+        assert!(state.just_pressed(Action::Fire));
+
+        state.update_actions(&map);
+        assert!(state.is_action_pressed(Action::Fire));
+        assert!(!state.just_pressed(Action::Fire));
+
+        state.release_key(Key::Space);
+        state.update_actions(&map);
+        assert!(state.just_released(Action::Fire));
+    }
+
+    #[test]
+    fn actions_can_be_rebound() {
+        let mut map = InputMap::default();
+        let mut state = InputState::default();
+        map.rebind(Action::Thrust, vec![Binding::Key(Key::K)]);
+
+        state.press_key(Key::Up, false);
+        state.update_actions(&map);
+        assert!(!state.is_action_pressed(Action::Thrust));
+
+        state.press_key(Key::K, false);
+        state.update_actions(&map);
+        assert!(state.is_action_pressed(Action::Thrust));
+    }
+
+    #[test]
+    fn gamepads_only_report_changes() {
+        let mut gamepad = Gamepad::default();
+        let mut state = GamepadState::default();
+        state.axes[0] = 0.05;
+        assert!(gamepad.update(state.clone()).is_empty());
+
+        state.axes[0] = 1.;
+        state.buttons[0] = true;
+        let events = gamepad.update(state.clone());
+        assert_eq!(events.len(), 2);
+        assert!(gamepad.update(state).is_empty());
+
+        let events = gamepad.update(GamepadState::default());
+        assert!(events.contains(&InputEvent::GamepadAxisEvent {
+            axis: GamepadAxis::LeftX,
+            value: 0.
+        }));
+    }
+
+    #[test]
+    fn touches_can_steer_and_fire_at_once() {
+        let map = InputMap::default();
+        let layout = TouchLayout::for_window(960, 540);
+        let mut state = InputState::default();
+
+        let stick = layout.stick;
+        state.touch_start(0, (stick.x as i32, stick.y as i32));
+        state.touch_move(0, (stick.x as i32, (stick.y - stick.radius * 2.) as i32));
+        state.touch_start(1, (layout.fire.x as i32, layout.fire.y as i32));
+        state.update_touch_controls(&layout);
+        state.update_actions(&map);
+        assert!(state.is_action_pressed(Action::Thrust));
+        assert!(state.is_action_pressed(Action::Fire));
+        assert_eq!(state.action_value(Action::Thrust), 1.);
+
+        state.touch_end(0);
+        state.update_touch_controls(&layout);
+        state.update_actions(&map);
+        assert!(!state.is_action_pressed(Action::Thrust));
+        assert!(state.is_action_pressed(Action::Fire));
+    }
+
+    #[test]
+    fn sticks_count_once_pushed_far_enough() {
+        let map = InputMap::default();
+        let mut state = InputState::default();
+
+        state.set_axis(GamepadAxis::LeftX, -0.2);
+        state.update_actions(&map);
+        assert!(!state.is_action_pressed(Action::RotateLeft));
+
+        state.set_axis(GamepadAxis::LeftX, -0.9);
+        state.update_actions(&map);
+        assert!(state.is_action_pressed(Action::RotateLeft));
     }
 }
@@ -0,0 +1,257 @@
+//! Level files describe the world a game starts with. They're written in RON, e.g.
+//!
+//! ```ron
+//! (
+//!     bounds: (100, 50),
+//!     player: (50., 25.),
+//!     entities: [
+//!         (kind: Boss, position: (20., 10.)),
+//!         (kind: Asteroid(size: Large), position: (50., 30.), rotation: 0.5),
+//!         (kind: Crystal, position: (35., 12.), velocity: (0.2, 0.1)),
+//!     ],
+//! )
+//! ```
+
+use std::fmt;
+
+use legion::World;
+use na::Vector2;
+use serde::{Deserialize, Serialize};
+
+use crate::components::{AsteroidSize, EntityTag, Transform};
+use crate::factories::{
+    AsteroidBuilder, BossBuilder, BulletBuilder, CrystalBuilder, EntityBuilder, PlayerBuilder,
+    WarriorBuilder, WorkerBuilder,
+};
+use crate::physics::Physics;
+use crate::resources::WorldBounds;
+
+/// The level the game starts with, unless it's given another one.
+pub const DEFAULT_LEVEL: &str = include_str!("../levels/default.ron");
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Level {
+    pub bounds: (u32, u32),
+    /// Where the player's ship starts out.
+    pub player: (f32, f32),
+    #[serde(default)]
+    pub entities: Vec<EntityRecord>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntityRecord {
+    pub kind: EntityKind,
+    pub position: (f32, f32),
+    #[serde(default)]
+    pub rotation: f32,
+    #[serde(default)]
+    pub velocity: (f32, f32),
+}
+
+/// What to create for a record, along with anything specific to that kind of entity.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum EntityKind {
+    Asteroid {
+        size: AsteroidSize,
+    },
+    Boss,
+    Worker,
+    Warrior,
+    Crystal,
+    /// Fired in the direction of the record's rotation.
+    Bullet {
+        speed: f32,
+        #[serde(default)]
+        hostile: bool,
+    },
+}
+
+impl EntityKind {
+    /// Whether records of this kind can start out with a velocity.
+    fn can_move(&self) -> bool {
+        match self {
+            EntityKind::Asteroid { .. } | EntityKind::Crystal => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum LevelError {
+    Parse(ron::Error),
+    /// Something's wrong with the level as a whole.
+    Invalid(String),
+    /// Something's wrong with `entities[index]`.
+    Record {
+        index: usize,
+        reason: String,
+    },
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LevelError::Parse(e) => write!(f, "couldn't parse level: {}", e),
+            LevelError::Invalid(reason) => write!(f, "invalid level: {}", reason),
+            LevelError::Record { index, reason } => {
+                write!(f, "invalid level: entities[{}]: {}", index, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LevelError {}
+
+impl From<ron::Error> for LevelError {
+    fn from(e: ron::Error) -> Self {
+        LevelError::Parse(e)
+    }
+}
+
+impl Level {
+    /// Parses and validates a level file.
+    pub fn parse(source: &str) -> Result<Level, LevelError> {
+        let level: Level = ron::de::from_str(source)?;
+        level.validate()?;
+        Ok(level)
+    }
+
+    pub fn world_bounds(&self) -> WorldBounds {
+        WorldBounds(Vector2::new(self.bounds.0, self.bounds.1))
+    }
+
+    fn contains(&self, (x, y): (f32, f32)) -> bool {
+        x >= 0. && y >= 0. && x < self.bounds.0 as f32 && y < self.bounds.1 as f32
+    }
+
+    pub fn validate(&self) -> Result<(), LevelError> {
+        if self.bounds.0 == 0 || self.bounds.1 == 0 {
+            return Err(LevelError::Invalid(format!(
+                "bounds {:?} leave no room for anything",
+                self.bounds
+            )));
+        }
+        if !self.contains(self.player) {
+            return Err(LevelError::Invalid(format!(
+                "player start {:?} is outside the bounds {:?}",
+                self.player, self.bounds
+            )));
+        }
+
+        let mut bosses = 0;
+        for (index, record) in self.entities.iter().enumerate() {
+            let fail = |reason: String| Err(LevelError::Record { index, reason });
+            let (vx, vy) = record.velocity;
+
+            if !self.contains(record.position) {
+                return fail(format!(
+                    "{:?} at {:?} is outside the bounds {:?}",
+                    record.kind, record.position, self.bounds
+                ));
+            }
+            if !record.rotation.is_finite() || !vx.is_finite() || !vy.is_finite() {
+                return fail(format!(
+                    "{:?} has a rotation or velocity that isn't a number",
+                    record.kind
+                ));
+            }
+            if (vx, vy) != (0., 0.) && !record.kind.can_move() {
+                return fail(format!("{:?} can't start out moving", record.kind));
+            }
+            match record.kind {
+                EntityKind::Boss => {
+                    bosses += 1;
+                    if bosses > 1 {
+                        return fail("there can only be one Sinistar".to_string());
+                    }
+                }
+                EntityKind::Bullet { speed, .. } if !speed.is_finite() || speed <= 0. => {
+                    return fail(format!("bullet speed {} should be more than 0", speed));
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates everything in the level, handing each record to the builder for its kind.
+    pub fn spawn(&self, world: &mut World, physics: &mut Physics) {
+        PlayerBuilder::starting_from(self.player.into()).create(world, physics);
+
+        let mut asteroids = AsteroidBuilder::default();
+        let mut workers = WorkerBuilder::default();
+        let mut warriors = WarriorBuilder::default();
+        let mut crystals = CrystalBuilder::default();
+        for record in &self.entities {
+            let t = Transform::from(record.position).with_rotation(record.rotation);
+            let velocity = Vector2::new(record.velocity.0, record.velocity.1);
+            match record.kind {
+                EntityKind::Asteroid { size } => {
+                    asteroids = asteroids.add_moving_asteroid(t, size, velocity)
+                }
+                EntityKind::Boss => BossBuilder::starting_from(t).create(world, physics),
+                EntityKind::Worker => workers = workers.add_worker(t),
+                EntityKind::Warrior => warriors = warriors.add_warrior(t),
+                EntityKind::Crystal => crystals = crystals.add_crystal(t, velocity),
+                EntityKind::Bullet { speed, hostile } => {
+                    let owner = if hostile {
+                        EntityTag::ENEMY
+                    } else {
+                        EntityTag::PLAYER
+                    };
+                    BulletBuilder::starting_from(t, speed)
+                        .fired_by(owner)
+                        .create(world, physics);
+                }
+            }
+        }
+        if !asteroids.is_empty() {
+            asteroids.create(world, physics);
+        }
+        workers.create(world, physics);
+        warriors.create(world, physics);
+        if !crystals.is_empty() {
+            crystals.create(world, physics);
+        }
+    }
+}
+
+impl Default for Level {
+    fn default() -> Self {
+        Level::parse(DEFAULT_LEVEL).expect("the default level should be valid")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn default_level_is_valid() {
+        let level = Level::parse(DEFAULT_LEVEL).unwrap();
+        assert_eq!(level.bounds, (100, 50));
+        assert!(!level.entities.is_empty());
+    }
+
+    #[test]
+    fn errors_point_at_the_bad_record() {
+        let source = "(
+            bounds: (100, 50),
+            player: (50., 25.),
+            entities: [
+                (kind: Asteroid(size: Large), position: (50., 30.)),
+                (kind: Worker, position: (10., 10.), velocity: (1., 0.)),
+            ],
+        )";
+        match Level::parse(source) {
+            Err(LevelError::Record { index, .. }) => assert_eq!(index, 1),
+            other => panic!("expected a record error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn errors_on_things_outside_the_world() {
+        let source = "(bounds: (100, 50), player: (150., 25.))";
+        assert!(matches!(Level::parse(source), Err(LevelError::Invalid(_))));
+    }
+}
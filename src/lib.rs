@@ -9,49 +9,61 @@ extern crate rapier2d;
 extern crate bitflags;
 
 // TODO:
-// damage
 // crabs
 // the void*
-// multi-sprite things
-// collecting stuff
-// AI
-// UI
 // collider shapes
-// animations
 
 use std::collections::HashMap;
+use std::time::Duration;
 
 #[cfg(target_arch = "wasm32")]
 use console_log;
 #[cfg(not(target_arch = "wasm32"))]
 use glfw::WindowEvent;
+use instant::Instant;
 use log::info;
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
 #[cfg(target_arch = "wasm32")]
-use web_sys::{KeyboardEvent, MouseEvent};
+use web_sys::{KeyboardEvent, MouseEvent, TouchEvent};
 
 #[macro_use]
 pub mod utils;
+pub mod audio;
 pub mod components;
 pub mod constants;
 pub mod event_queue;
+pub mod events;
 pub mod factories;
 pub mod input;
+pub mod level;
+pub mod overlay;
+pub mod particles;
 pub mod physics;
 pub mod renderer;
+pub mod replay;
 pub mod resources;
+pub mod snapshot;
 pub mod spritesheet;
 pub mod systems;
 pub mod types;
 
-use crate::factories::{AsteroidBuilder, EntityBuilder, PlayerBuilder};
+use crate::audio::{AudioBackend, NullBackend};
+use crate::constants::{FIXED_TIMESTEP, MAX_FRAME_TIME};
+use crate::event_queue::Drain;
+use crate::factories::EntityBuilder;
 #[cfg(target_arch = "wasm32")]
-use crate::input::KeyState;
-use crate::input::{InputEvent, InputState};
+use crate::input::{touch_events, KeyState, TouchPhase};
+use crate::input::{Gamepad, GamepadState, InputEvent, InputMap, InputState};
+use crate::level::Level;
+use crate::particles::Particles;
 use crate::physics::Physics;
+use crate::replay::{Playback, Replay};
 use crate::resources::*;
-use crate::systems::init as init_systems;
+use crate::snapshot::{Snapshot, SnapshotError};
+use crate::systems::{
+    init as init_systems, init_attract as init_attract_systems, init_frame as init_frame_systems,
+};
 use crate::types::*;
 
 // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global
@@ -73,59 +85,108 @@ pub struct Game {
     world: legion::World,
     resources: legion::Resources,
     schedule: legion::Schedule,
-    renderer: renderer::Renderer,
+    attract_schedule: legion::Schedule,
+    frame_schedule: legion::Schedule,
+    renderer: Option<renderer::Renderer>,
+    last_tick: Instant,
+    accumulator: Duration,
+    // what the game was started from, for replays
+    seed: u64,
+    level: Level,
+    recording: Option<Replay>,
+    playback: Option<Playback>,
+    gamepad: Gamepad,
+    audio: Box<dyn AudioBackend>,
 }
 
 #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
 impl Game {
     pub fn new() -> Self {
-        info!("Creating game!");
+        Self::with_level(&Level::default())
+    }
 
-        let mut world = legion::World::default();
-        let mut physics = Physics::default();
-        let world_bounds = WorldBounds::default();
-        let window_dimensions = WindowDimensions::default();
+    /// Creates a game from the RON level file in `source`.
+    #[cfg(target_arch = "wasm32")]
+    pub fn from_level(source: &str) -> Result<Game, JsValue> {
+        let level = Level::parse(source).map_err(|e| JsValue::from_str(&e.to_string()))?;
+        Ok(Self::with_level(&level))
+    }
 
-        PlayerBuilder::starting_from((world_bounds.as_f32() / 2.0).into())
-            .create(&mut world, &mut physics);
-        AsteroidBuilder::default()
-            .add_asteroid((50., 30.))
-            .add_asteroid((45., 30.))
-            .add_asteroid((55., 30.))
-            .create(&mut world, &mut physics);
-        let mut resources = legion::Resources::default();
-        resources.insert(InputState::default());
-        resources.insert(InputEventQueue::default());
-        resources.insert(physics);
-        resources.insert(world_bounds);
-        resources.insert(window_dimensions);
-        resources.insert(ViewMatrix::default());
+    /// Creates a game that plays back the replay file in `bytes`.
+    #[cfg(target_arch = "wasm32")]
+    pub fn from_replay(bytes: &[u8]) -> Result<Game, JsValue> {
+        let replay = Replay::from_bytes(bytes).map_err(|e| JsValue::from_str(&e.to_string()))?;
+        Ok(Self::headless_from_replay(replay).with_renderer())
+    }
 
-        Game {
-            renderer: renderer::Renderer::new(&window_dimensions),
-            world,
-            resources,
-            schedule: init_systems(),
-        }
+    /// Creates a game without a renderer, so no window or canvas is needed. Ticking a headless
+    /// game only runs the schedules.
+    pub fn headless() -> Self {
+        Self::headless_with_level(&Level::default())
     }
 
+    /// Advances the game by however much wall-clock time passed since the last tick, then renders
+    /// a frame.
     pub fn tick(&mut self) {
-        self.schedule.execute(&mut self.world, &mut self.resources);
-        self.renderer.draw(&mut self.world, &self.resources);
+        let now = Instant::now();
+        let frame_time = now - self.last_tick;
+        self.last_tick = now;
+        self.advance(frame_time);
+    }
 
-        //let mut physics = self.resources.get_mut::<Physics>().unwrap();
-        //physics.cleanup(&mut self.world);
+    /// Starts a game from the attract screen or after a game over, as if fire had been pressed.
+    /// Like any other input, it's handled on the next step, and replays record it.
+    pub fn start(&mut self) {
+        self.queue_input(InputEvent::StartGameEvent);
+    }
+
+    /// Saves the whole simulation, for quicksaves or attaching to bug reports.
+    pub fn save(&self) -> Vec<u8> {
+        self.snapshot().to_bytes()
+    }
+
+    /// Replaces the simulation with one saved by `save`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(&mut self, bytes: &[u8]) -> Result<(), SnapshotError> {
+        self.restore(Snapshot::from_bytes(bytes)?)
+    }
+    #[cfg(target_arch = "wasm32")]
+    pub fn load(&mut self, bytes: &[u8]) -> Result<(), JsValue> {
+        Snapshot::from_bytes(bytes)
+            .and_then(|snapshot| self.restore(snapshot))
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// Starts recording input for a replay. Replays play back from the start of the level, so
+    /// this has to be called before the first step.
+    pub fn start_recording(&mut self) {
+        let frame = self.resources.get::<Time>().unwrap().frame;
+        assert_eq!(frame, 0, "recording has to start before the first step");
+        let input_map = self.resources.get::<InputMap>().unwrap().clone();
+        self.recording = Some(Replay::new(self.seed, self.level.clone(), input_map));
+    }
+
+    /// Stops recording, returning the replay file if the game was being recorded.
+    pub fn finish_recording(&mut self) -> Option<Vec<u8>> {
+        self.recording.take().map(|replay| replay.to_bytes())
+    }
+
+    /// Replaces the key bindings with ones read from `source`, in the format of
+    /// `config/input.ron`.
+    #[cfg(target_arch = "wasm32")]
+    pub fn load_input_map(&mut self, source: &str) -> Result<(), JsValue> {
+        let map = InputMap::parse(source).map_err(|e| JsValue::from_str(&e.to_string()))?;
+        self.set_input_map(map);
+        Ok(())
     }
 
     #[cfg(not(target_arch = "wasm32"))]
     pub fn log_event(&mut self, e: InputEvent) {
-        let input_q = self.resources.get_or_default::<InputEventQueue>();
-        input_q.push(e);
+        self.queue_input(e);
     }
     #[cfg(target_arch = "wasm32")]
     fn log_event(&mut self, e: InputEvent) {
-        let input_q = self.resources.get_or_default::<InputEventQueue>();
-        input_q.push(e);
+        self.queue_input(e);
     }
 
     #[cfg(target_arch = "wasm32")]
@@ -146,6 +207,54 @@ impl Game {
         self.log_event(ie);
     }
 
+    #[cfg(target_arch = "wasm32")]
+    pub fn log_mousemove_event(&mut self, e: MouseEvent) {
+        self.log_event(InputEvent::MouseMoveEvent {
+            x: e.offset_x(),
+            y: e.offset_y(),
+        });
+    }
+
+    /// Takes a gamepad's state from the browser's Gamepad API, in its "standard" layout. Called
+    /// every frame, with empty arrays once the gamepad's gone.
+    #[cfg(target_arch = "wasm32")]
+    pub fn log_gamepad_state(&mut self, axes: &[f32], buttons: &[f32]) {
+        self.update_gamepad(GamepadState::from_standard_mapping(axes, buttons));
+    }
+
+    /// Checks the first gamepad GLFW knows about. Called every frame, like `iter_events`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn poll_gamepad(&mut self) {
+        let state = self
+            .renderer
+            .as_ref()
+            .and_then(|renderer| renderer.gamepad_state())
+            .unwrap_or_default();
+        self.update_gamepad(state);
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub fn log_touchstart_event(&mut self, e: TouchEvent) {
+        for ie in touch_events(&e, TouchPhase::Started) {
+            self.log_event(ie);
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub fn log_touchmove_event(&mut self, e: TouchEvent) {
+        for ie in touch_events(&e, TouchPhase::Moved) {
+            self.log_event(ie);
+        }
+    }
+
+    /// For `touchcancel` as well as `touchend`.
+    #[cfg(target_arch = "wasm32")]
+    pub fn log_touchend_event(&mut self, e: TouchEvent) {
+        for ie in touch_events(&e, TouchPhase::Ended) {
+            self.log_event(ie);
+        }
+    }
+
     #[cfg(target_arch = "wasm32")]
     pub fn log_keydown_event(&mut self, e: KeyboardEvent) {
         let ie: InputEvent = e.into();
@@ -166,7 +275,282 @@ impl Game {
 
     #[cfg(not(target_arch = "wasm32"))]
     pub fn iter_events(&mut self) -> std::sync::mpsc::TryIter<(f64, WindowEvent)> {
-        self.renderer.iter_events()
+        self.renderer
+            .as_mut()
+            .expect("headless games have no window events")
+            .iter_events()
+    }
+}
+
+// Not exported to JS: these hand out references to the ECS, which is mostly useful for tests.
+impl Game {
+    /// Creates a game from a level, with a window or canvas to draw it in.
+    pub fn with_level(level: &Level) -> Self {
+        Self::headless_with_level(level).with_renderer()
+    }
+
+    /// Gives a headless game a window or canvas to draw in.
+    pub fn with_renderer(mut self) -> Self {
+        let window_dimensions = *self.resources.get::<WindowDimensions>().unwrap();
+        self.renderer = Some(renderer::Renderer::new(&window_dimensions));
+        self.audio = audio::default_backend();
+        self
+    }
+
+    /// Like `headless`, but starting from `level` instead of the default one.
+    pub fn headless_with_level(level: &Level) -> Self {
+        Self::headless_seeded(level, rand::random())
+    }
+
+    /// Creates a headless game that plays back a replay. Input logged while it plays is ignored.
+    pub fn headless_from_replay(replay: Replay) -> Self {
+        let mut game = Self::headless_seeded(&replay.header.level, replay.header.seed);
+        game.set_input_map(replay.header.input_map.clone());
+        game.playback = Some(Playback::new(replay));
+        game
+    }
+
+    /// Like `headless_with_level`, with everything random in the simulation coming from `seed`.
+    pub fn headless_seeded(level: &Level, seed: u64) -> Self {
+        info!("Creating game with seed {}", seed);
+
+        let mut world = legion::World::default();
+        let mut physics = Physics::default();
+        let world_bounds = level.world_bounds();
+        let window_dimensions = WindowDimensions::default();
+
+        // asteroids and enemies come in waves once the game starts, so levels only need what's
+        // there from the beginning
+        level.spawn(&mut world, &mut physics);
+        let mut resources = legion::Resources::default();
+        resources.insert(InputState::default());
+        resources.insert(InputMap::default());
+        resources.insert(InputEventQueue::default());
+        resources.insert(GameEventQueue::default());
+        resources.insert(SoundEventQueue::default());
+        resources.insert(physics);
+        resources.insert(world_bounds);
+        resources.insert(window_dimensions);
+        resources.insert(ViewMatrix::default());
+        resources.insert(Time::default());
+        resources.insert(Random::seeded(seed));
+        resources.insert(GameState::default());
+        resources.insert(Session::default());
+        resources.insert(WaveDirector::default());
+        resources.insert(Particles::default());
+
+        Game {
+            renderer: None,
+            world,
+            resources,
+            schedule: init_systems(),
+            attract_schedule: init_attract_systems(),
+            frame_schedule: init_frame_systems(),
+            last_tick: Instant::now(),
+            accumulator: Duration::default(),
+            seed,
+            level: level.clone(),
+            recording: None,
+            playback: None,
+            gamepad: Gamepad::default(),
+            audio: Box::new(NullBackend),
+        }
+    }
+
+    /// Runs as many fixed simulation steps as fit in `frame_time` (plus whatever was left over
+    /// from previous frames), then runs the per-frame systems and renders once.
+    pub fn advance(&mut self, frame_time: Duration) {
+        let frame_time = frame_time.min(MAX_FRAME_TIME);
+        {
+            let mut time = self.resources.get_mut::<Time>().unwrap();
+            time.frame_delta = frame_time;
+            self.accumulator += frame_time.mul_f32(time.time_scale);
+        }
+
+        while self.accumulator >= FIXED_TIMESTEP {
+            self.accumulator -= FIXED_TIMESTEP;
+            self.step();
+        }
+
+        self.frame_schedule
+            .execute(&mut self.world, &mut self.resources);
+        if let Some(renderer) = &mut self.renderer {
+            renderer.draw(&mut self.world, &self.resources);
+        }
+    }
+
+    /// Runs exactly one fixed simulation step, without rendering. The world only moves while a
+    /// game is in progress.
+    pub fn step(&mut self) {
+        let frame = self.resources.get::<Time>().unwrap().frame;
+        if let Some(playback) = &mut self.playback {
+            let input_q = self.resources.get::<InputEventQueue>().unwrap();
+            for e in playback.events_for(frame) {
+                input_q.push(e);
+            }
+        }
+
+        let state = *self.resources.get::<GameState>().unwrap();
+        let schedule = if state.in_game() {
+            &mut self.schedule
+        } else {
+            &mut self.attract_schedule
+        };
+        schedule.execute(&mut self.world, &mut self.resources);
+        let starting = *self.resources.get::<GameState>().unwrap() == GameState::Starting;
+        if starting {
+            self.restart();
+        }
+
+        for e in self
+            .resources
+            .get::<GameEventQueue>()
+            .unwrap()
+            .get_mut()
+            .drain()
+        {
+            debug!("{:?}", e);
+        }
+        for sound in self
+            .resources
+            .get::<SoundEventQueue>()
+            .unwrap()
+            .get_mut()
+            .drain()
+        {
+            self.audio.play(sound);
+        }
+        self.resources
+            .get_mut::<Physics>()
+            .unwrap()
+            .cleanup(&mut self.world);
+
+        if self.recording.is_some() || self.playback.is_some() {
+            let hash = replay::world_hash(&self.world, &self.resources);
+            if let Some(recording) = &mut self.recording {
+                recording.hashes.push(hash);
+            }
+            if let Some(playback) = &mut self.playback {
+                playback.check(frame, hash);
+            }
+        }
+
+        // the simulation clock stops while the game is paused, so nothing times out behind the
+        // player's back
+        let mut time = self.resources.get_mut::<Time>().unwrap();
+        if state != GameState::Paused {
+            time.elapsed += time.delta;
+        }
+        time.frame += 1;
+    }
+
+    pub fn world(&self) -> &legion::World {
+        &self.world
+    }
+
+    pub fn world_mut(&mut self) -> &mut legion::World {
+        &mut self.world
+    }
+
+    pub fn resources(&self) -> &legion::Resources {
+        &self.resources
+    }
+
+    pub fn resources_mut(&mut self) -> &mut legion::Resources {
+        &mut self.resources
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot::capture(&self.world, &self.resources)
+    }
+
+    /// Throws away what's left of the last game and starts the level over, keeping the clock and
+    /// the random number generator going so that replays still line up.
+    fn restart(&mut self) {
+        info!("Restarting the level");
+        self.world.clear();
+        let mut physics = Physics::default();
+        self.level.spawn(&mut self.world, &mut physics);
+        self.resources.insert(physics);
+        self.resources.insert(Session::default());
+        self.resources.insert(WaveDirector::default());
+        self.resources.get_mut::<Particles>().unwrap().clear();
+        self.resources.insert(GameState::Playing);
+        // systems keep some state of their own, like weapon cooldowns
+        self.schedule = init_systems();
+    }
+
+    /// Puts the simulation back the way it was when `snapshot` was taken.
+    pub fn restore(&mut self, snapshot: Snapshot) -> Result<(), SnapshotError> {
+        snapshot.restore(&mut self.world, &mut self.resources)?;
+        // replays only work from the start of a level
+        if self.recording.take().is_some() || self.playback.take().is_some() {
+            warn!("Restoring a snapshot stopped the replay");
+        }
+        // systems keep some state of their own, like weapon cooldowns, which would be out of step
+        // with the restored clock
+        self.schedule = init_systems();
+        self.attract_schedule = init_attract_systems();
+        self.accumulator = Duration::default();
+        Ok(())
+    }
+
+    /// The first frame where a replay played out differently from its recording, if it has.
+    pub fn replay_diverged_at(&self) -> Option<u64> {
+        self.playback.as_ref().and_then(|p| p.diverged_at())
+    }
+
+    /// Whether a replay has played all of its recorded frames.
+    pub fn replay_finished(&self) -> bool {
+        let frame = self.resources.get::<Time>().unwrap().frame;
+        self.playback
+            .as_ref()
+            .map_or(false, |p| p.is_finished(frame))
+    }
+
+    /// Replaces the key bindings. Replays keep the bindings they were recorded with, so this
+    /// should be called before recording starts.
+    pub fn set_input_map(&mut self, map: InputMap) {
+        if self.recording.is_some() {
+            warn!("Changing bindings partway through a recording, it won't play back the same");
+        }
+        self.resources.insert(map);
+    }
+
+    /// Replaces whatever plays the game's sounds, e.g. with a `RecordingBackend` in tests.
+    pub fn set_audio_backend(&mut self, backend: Box<dyn AudioBackend>) {
+        self.audio = backend;
+    }
+
+    /// Logs whatever changed on the gamepad since it was last seen.
+    pub fn update_gamepad(&mut self, state: GamepadState) {
+        for e in self.gamepad.update(state) {
+            self.queue_input(e);
+        }
+    }
+
+    fn queue_input(&mut self, e: InputEvent) {
+        // while a replay plays, its input is the only input
+        if self.playback.is_some() {
+            return;
+        }
+        if let Some(recording) = &mut self.recording {
+            // queued input is handled on the next step
+            let frame = self.resources.get::<Time>().unwrap().frame;
+            recording.events.push((frame, e.clone()));
+        }
+        let input_q = self.resources.get_or_default::<InputEventQueue>();
+        input_q.push(e);
+    }
+
+    /// Adds entities to the world, along with their rigid bodies.
+    pub fn spawn<B: EntityBuilder>(&mut self, builder: B) {
+        let mut physics = self.resources.get_mut::<Physics>().unwrap();
+        builder.create(&mut self.world, &mut physics);
+    }
+
+    pub fn is_headless(&self) -> bool {
+        self.renderer.is_none()
     }
 }
 
@@ -0,0 +1,471 @@
+//! Things drawn over the world in screen space, which is in pixels from the top left of the
+//! window, the same as input positions.
+
+use legion::*;
+use na::Vector2;
+
+use crate::components::{CompositeSprite, EntityTag, Health, Inventory, Player, Transform};
+use crate::constants::SPRITES_PER_HALF_SCREEN;
+use crate::input::{InputState, TouchCircle, TouchInput, TouchLayout};
+use crate::resources::{GameState, Session, WaveDirector, WindowDimensions, WorldBounds};
+use crate::types::ViewMatrix;
+
+pub const SHIP_SPRITE: usize = 1;
+pub const SINIBOMB_SPRITE: usize = 10;
+pub const RING_SPRITE: usize = 11;
+pub const DISC_SPRITE: usize = 12;
+pub const BLOCK_SPRITE: usize = 13;
+
+/// Each glyph is 5x7 pixels, in an 8x8 cell. The atlas starts at ' ' and goes in rows of 8 up to
+/// '_', which covers numbers, capitals and most punctuation.
+pub const FONT: &[u8] = include_bytes!("font.png");
+pub const GLYPH_CELL: u32 = 8;
+const FONT_COLUMNS: usize = 8;
+const FONT_ROWS: usize = 8;
+const FIRST_GLYPH: u8 = b' ';
+const LAST_GLYPH: u8 = b'_';
+// in unscaled pixels
+const GLYPH_WIDTH: f32 = 5.;
+const GLYPH_ADVANCE: f32 = 6.;
+const LINE_HEIGHT: f32 = 10.;
+
+pub const TEXT_COLOR: [f32; 3] = [1., 1., 1.];
+
+const IDLE_COLOR: [f32; 3] = [0.3, 0.3, 0.3];
+const PRESSED_COLOR: [f32; 3] = [0.8, 0.8, 0.8];
+// the stick's knob, as a fraction of the whole stick
+const KNOB_SCALE: f32 = 0.4;
+
+// the HUD is laid out for this window height, and scaled to fit others
+const HUD_HEIGHT: f32 = 270.;
+// in HUD pixels
+const HUD_MARGIN: f32 = 6.;
+const HUD_ICON: f32 = 8.;
+const HEALTH_SEGMENT: f32 = 3.;
+const HEALTH_LOW_COLOR: [f32; 3] = [1., 0.3, 0.3];
+const SCANNER_WIDTH: f32 = 80.;
+const BLIP_SIZE: f32 = 1.5;
+// the scanner's frame is dotted, with gaps as big as the dots
+const FRAME_DOT: f32 = 0.5;
+
+// scanner blips, by tag. The first matching entry wins, and anything else isn't shown.
+const BLIP_COLORS: [(EntityTag, [f32; 3]); 5] = [
+    (EntityTag::BOSS, [1., 0.6, 0.1]),
+    (EntityTag::PLAYER, [1., 1., 1.]),
+    (EntityTag::CRYSTAL, [0.4, 0.9, 1.]),
+    (EntityTag::ENEMY, [1., 0.25, 0.25]),
+    (EntityTag::ASTEROID, [0.5, 0.5, 0.5]),
+];
+
+/// Everything drawn over the world for a frame.
+#[derive(Debug, Default)]
+pub struct Overlay {
+    pub sprites: Vec<ScreenSprite>,
+    pub texts: Vec<Text>,
+}
+
+impl Overlay {
+    /// Reads whatever needs showing out of the world and its resources.
+    pub fn gather(world: &World, resources: &Resources) -> Self {
+        let dims = *resources.get::<WindowDimensions>().unwrap();
+        let state = *resources.get::<GameState>().unwrap();
+        let mut overlay = Overlay::default();
+        if state != GameState::Attract {
+            overlay.add_hud(world, resources, &dims);
+        }
+        overlay.sprites.extend(touch_controls(
+            &resources.get::<InputState>().unwrap(),
+            &dims,
+        ));
+        overlay.texts.extend(messages(state, &dims));
+        overlay
+    }
+
+    /// Score and wave along the top, lives and bombs under the score, and the ship's health in the
+    /// middle. Everything's placed relative to the window's edges, so it stays put whatever shape
+    /// the window is.
+    fn add_hud(&mut self, world: &World, resources: &Resources, dims: &WindowDimensions) {
+        let session = *resources.get::<Session>().unwrap();
+        let director = *resources.get::<WaveDirector>().unwrap();
+        let player = <(&Player, &Health, &Inventory)>::query()
+            .iter(world)
+            .next()
+            .map(|(_, health, inventory)| (health.0, inventory.bombs));
+
+        let scale = (dims.h as f32 / HUD_HEIGHT).max(1.);
+        let margin = HUD_MARGIN * scale;
+        let w = dims.w as f32;
+        let text = |content: String, x: f32, y: f32| Text::new(content, x, y).scale(scale);
+
+        self.texts
+            .push(text(format!("SCORE {:06}", session.score), margin, margin));
+        self.texts
+            .push(text(format!("WAVE {}", director.wave), w - margin, margin).align(Align::Right));
+
+        // a row of little icons under the score, each with a count
+        let icon = HUD_ICON * scale;
+        let row = margin * 2. + LINE_HEIGHT * scale;
+        let mut counter = |index: usize, count: u32, x: f32| {
+            self.sprites.push(ScreenSprite {
+                index,
+                x: x + icon / 2.,
+                y: row + icon / 2.,
+                size: icon,
+                color: TEXT_COLOR,
+            });
+            self.texts
+                .push(text(format!("X{}", count), x + icon + scale, row + scale));
+        };
+        counter(SHIP_SPRITE, session.lives, margin);
+        if let Some((_, bombs)) = player {
+            counter(SINIBOMB_SPRITE, bombs, margin + icon * 4.);
+        }
+
+        let segment = HEALTH_SEGMENT * scale;
+        let scanner_top = margin * 2. + segment;
+        self.add_scanner(world, resources, w / 2., scanner_top, scale);
+
+        // one segment per point of health, centered along the top
+        if let Some((health, _)) = player {
+            let step = segment + scale;
+            let left = (w - Player::HEALTH as f32 * step) / 2.;
+            let color = if health * 3 <= Player::HEALTH {
+                HEALTH_LOW_COLOR
+            } else {
+                TEXT_COLOR
+            };
+            for i in 0..Player::HEALTH {
+                self.sprites.push(ScreenSprite {
+                    index: BLOCK_SPRITE,
+                    x: left + i as f32 * step + segment / 2.,
+                    y: margin + segment / 2.,
+                    size: segment,
+                    color: if i < health { color } else { IDLE_COLOR },
+                });
+            }
+        }
+    }
+
+    /// The whole world in miniature, centered on the middle of the view like the world around the
+    /// ship is, with a blip for everything worth knowing about and a frame around what's on screen.
+    fn add_scanner(&mut self, world: &World, resources: &Resources, x: f32, top: f32, scale: f32) {
+        let bounds = *resources.get::<WorldBounds>().unwrap();
+        let dims = *resources.get::<WindowDimensions>().unwrap();
+        let view = resources.get::<ViewMatrix>().unwrap().0;
+        // the view moves the world so the middle of the screen is at the origin
+        let center = -Vector2::new(view[(0, 3)], view[(1, 3)]);
+        let scanner = Scanner::new(bounds, center, x, top, SCANNER_WIDTH * scale);
+
+        let dot = FRAME_DOT * scale;
+        let size = bounds.as_f32() * scanner.scale;
+        self.dotted_rect(x, top + size.y / 2., size, dot, IDLE_COLOR);
+        let view_size = Vector2::new(
+            SPRITES_PER_HALF_SCREEN * 2.,
+            SPRITES_PER_HALF_SCREEN * 2. / dims.aspect_ratio,
+        ) * scanner.scale;
+        self.dotted_rect(x, top + size.y / 2., view_size, dot, TEXT_COLOR);
+
+        let mut blip = |position: Vector2<f32>, color: [f32; 3]| {
+            let (x, y) = scanner.place(position);
+            self.sprites.push(ScreenSprite {
+                index: BLOCK_SPRITE,
+                x,
+                y,
+                size: BLIP_SIZE * scale,
+                color,
+            });
+        };
+        for (tag, t, parts) in
+            <(&EntityTag, &Transform, Option<&CompositeSprite>)>::query().iter(world)
+        {
+            let color = match BLIP_COLORS.iter().find(|(t, _)| tag.contains(*t)) {
+                Some((_, color)) => *color,
+                None => continue,
+            };
+            let position = t.isometry.translation.vector.xy();
+            match parts {
+                // the Sinistar shows up a piece at a time, as it's built
+                Some(parts) => {
+                    for part in parts.parts.iter().filter(|p| p.visible) {
+                        blip(position + part.offset, color);
+                    }
+                }
+                None => blip(position, color),
+            }
+        }
+    }
+
+    /// A dotted outline, `size` pixels across, around (`x`, `y`).
+    fn dotted_rect(&mut self, x: f32, y: f32, size: Vector2<f32>, dot: f32, color: [f32; 3]) {
+        let (left, top) = (x - size.x / 2., y - size.y / 2.);
+        let mut dot_at = |x: f32, y: f32| {
+            self.sprites.push(ScreenSprite {
+                index: BLOCK_SPRITE,
+                x,
+                y,
+                size: dot,
+                color,
+            })
+        };
+        let step = dot * 2.;
+        for i in 0..=(size.x / step) as usize {
+            let x = left + i as f32 * step;
+            dot_at(x, top);
+            dot_at(x, top + size.y);
+        }
+        for i in 1..(size.y / step) as usize {
+            let y = top + i as f32 * step;
+            dot_at(left, y);
+            dot_at(left + size.x, y);
+        }
+    }
+}
+
+/// Maps the world onto the scanner, wrapping it around so that `center` is in the middle.
+#[derive(Debug, Copy, Clone)]
+struct Scanner {
+    bounds: WorldBounds,
+    center: Vector2<f32>,
+    /// The middle of the scanner, on screen.
+    x: f32,
+    y: f32,
+    /// Pixels per world unit.
+    scale: f32,
+}
+
+impl Scanner {
+    fn new(bounds: WorldBounds, center: Vector2<f32>, x: f32, top: f32, width: f32) -> Self {
+        let scale = width / bounds.as_f32().x;
+        Scanner {
+            bounds,
+            center,
+            x,
+            y: top + bounds.as_f32().y * scale / 2.,
+            scale,
+        }
+    }
+
+    /// Where something at `position` in the world shows up on screen.
+    fn place(&self, position: Vector2<f32>) -> (f32, f32) {
+        let offset = self.bounds.shortest_offset(self.center, position) * self.scale;
+        // the world's y axis points up, and the screen's down
+        (self.x + offset.x, self.y - offset.y)
+    }
+}
+
+/// A spritesheet cell drawn somewhere on the screen, rather than in the world.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ScreenSprite {
+    pub index: usize,
+    /// Where the middle of the sprite goes.
+    pub x: f32,
+    pub y: f32,
+    /// Width and height, in pixels.
+    pub size: f32,
+    pub color: [f32; 3],
+}
+
+/// Which side of a line of text its `x` is on.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+/// A string drawn with the bitmap font, e.g.
+/// `Text::new("PAUSED", x, y).align(Align::Center).scale(4.)`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Text {
+    pub content: String,
+    pub x: f32,
+    /// The top of the first line.
+    pub y: f32,
+    /// How many screen pixels each of the font's pixels takes up.
+    pub scale: f32,
+    pub align: Align,
+    pub color: [f32; 3],
+}
+
+impl Text {
+    pub fn new(content: impl Into<String>, x: f32, y: f32) -> Self {
+        Text {
+            content: content.into(),
+            x,
+            y,
+            scale: 2.,
+            align: Align::Left,
+            color: TEXT_COLOR,
+        }
+    }
+
+    pub fn scale(mut self, scale: f32) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    pub fn align(mut self, align: Align) -> Self {
+        self.align = align;
+        self
+    }
+
+    pub fn color(mut self, color: [f32; 3]) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// How wide `line` is once it's drawn, in pixels.
+    pub fn line_width(&self, line: &str) -> f32 {
+        match line.chars().count() {
+            0 => 0.,
+            n => ((n - 1) as f32 * GLYPH_ADVANCE + GLYPH_WIDTH) * self.scale,
+        }
+    }
+
+    pub fn line_height(&self) -> f32 {
+        LINE_HEIGHT * self.scale
+    }
+
+    /// Where each character's cell in the font atlas goes. Characters the font doesn't have are
+    /// drawn as '?', and lower case as upper case.
+    pub fn glyphs(&self) -> Vec<ScreenSprite> {
+        let size = GLYPH_CELL as f32 * self.scale;
+        let mut glyphs = vec![];
+        for (row, line) in self.content.lines().enumerate() {
+            let width = self.line_width(line);
+            let left = match self.align {
+                Align::Left => self.x,
+                Align::Center => self.x - width / 2.,
+                Align::Right => self.x - width,
+            };
+            let top = self.y + row as f32 * self.line_height();
+            for (i, c) in line.chars().enumerate() {
+                if c == ' ' {
+                    continue;
+                }
+                glyphs.push(ScreenSprite {
+                    index: glyph_index(c),
+                    x: left + i as f32 * GLYPH_ADVANCE * self.scale + size / 2.,
+                    y: top + size / 2.,
+                    size,
+                    color: self.color,
+                });
+            }
+        }
+        glyphs
+    }
+}
+
+/// Which cell of the font atlas has `c`.
+fn glyph_index(c: char) -> usize {
+    let c = c.to_ascii_uppercase();
+    let code = if c.is_ascii() && (FIRST_GLYPH..=LAST_GLYPH).contains(&(c as u8)) {
+        c as u8
+    } else {
+        b'?'
+    };
+    let i = (code - FIRST_GLYPH) as usize;
+    // the atlas is flipped over when it's loaded, so `Spritesheet` counts rows from the bottom
+    (FONT_ROWS - 1 - i / FONT_COLUMNS) * FONT_COLUMNS + i % FONT_COLUMNS
+}
+
+fn color(pressed: bool) -> [f32; 3] {
+    if pressed {
+        PRESSED_COLOR
+    } else {
+        IDLE_COLOR
+    }
+}
+
+fn button(circle: TouchCircle, pressed: bool) -> ScreenSprite {
+    ScreenSprite {
+        index: DISC_SPRITE,
+        x: circle.x,
+        y: circle.y,
+        size: circle.radius * 2.,
+        color: color(pressed),
+    }
+}
+
+/// The virtual stick and buttons, once the player has touched the screen.
+fn touch_controls(input: &InputState, dims: &WindowDimensions) -> Vec<ScreenSprite> {
+    if !input.uses_touch() {
+        return vec![];
+    }
+    let layout = TouchLayout::for_window(dims.w, dims.h);
+    let stick = layout.stick;
+    let (x, y) = input.touch_stick();
+    let pushed = (x, y) != (0., 0.);
+    vec![
+        ScreenSprite {
+            index: RING_SPRITE,
+            x: stick.x,
+            y: stick.y,
+            size: stick.radius * 2.,
+            color: color(pushed),
+        },
+        ScreenSprite {
+            index: DISC_SPRITE,
+            // the stick points up for positive values, and the screen goes down
+            x: stick.x + x * stick.radius,
+            y: stick.y - y * stick.radius,
+            size: stick.radius * 2. * KNOB_SCALE,
+            color: color(pushed),
+        },
+        button(layout.fire, input.is_touch_button_pressed(TouchInput::Fire)),
+        button(layout.bomb, input.is_touch_button_pressed(TouchInput::Bomb)),
+    ]
+}
+
+/// What to tell the player when they're not just playing.
+fn messages(state: GameState, dims: &WindowDimensions) -> Vec<Text> {
+    let (x, y) = (dims.w as f32 / 2., dims.h as f32 / 3.);
+    let title = |content: &str| Text::new(content, x, y).align(Align::Center).scale(6.);
+    let hint = |content: &str| {
+        Text::new(content, x, y + 80.)
+            .align(Align::Center)
+            .color(IDLE_COLOR)
+    };
+    match state {
+        GameState::Attract => vec![title("VOIDSTAR"), hint("PRESS FIRE TO START")],
+        GameState::Paused => vec![title("PAUSED")],
+        GameState::GameOver => vec![title("GAME OVER"), hint("PRESS FIRE TO PLAY AGAIN")],
+        _ => vec![],
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn text_lines_up_with_its_anchor() {
+        let text = Text::new("AB", 100., 0.).scale(1.);
+        assert_eq!(text.line_width("AB"), 11.);
+
+        let right = text.clone().align(Align::Right).glyphs();
+        let last = right.last().unwrap();
+        // the glyph is 5 pixels wide, in the left of an 8 pixel cell
+        assert_eq!(last.x - last.size / 2. + GLYPH_WIDTH, 100.);
+
+        let centered = text.align(Align::Center).glyphs();
+        assert_eq!(centered[0].x - centered[0].size / 2., 94.5);
+    }
+
+    #[test]
+    fn the_scanner_wraps_around_the_world() {
+        let bounds = WorldBounds(Vector2::new(100, 50));
+        let scanner = Scanner::new(bounds, Vector2::new(95., 25.), 100., 0., 100.);
+        assert_eq!(scanner.place(Vector2::new(95., 25.)), (100., 25.));
+        // just over the right edge of the world is just to the right of the middle
+        assert_eq!(scanner.place(Vector2::new(5., 30.)), (110., 20.));
+    }
+
+    #[test]
+    fn missing_characters_fall_back() {
+        assert_eq!(glyph_index('a'), glyph_index('A'));
+        assert_eq!(glyph_index('~'), glyph_index('?'));
+        // ' ' is the top left of the atlas, which ends up on the bottom row
+        assert_eq!(glyph_index(' '), (FONT_ROWS - 1) * FONT_COLUMNS);
+    }
+}
@@ -0,0 +1,10 @@
+in vec2 v_tex_co;
+in vec4 v_color;
+out vec4 color;
+
+uniform sampler2D image;
+
+void main()
+{
+    color = v_color * texture(image, v_tex_co);
+}
@@ -0,0 +1,29 @@
+in vec2 co;
+in vec2 tex_co;
+in vec2 particle_position;
+in vec4 particle_color;
+in float particle_size;
+
+out vec2 v_tex_co;
+out vec4 v_color;
+
+// see texture-vs.glsl for why the matrices come in columns
+uniform vec4 pc0;
+uniform vec4 pc1;
+uniform vec4 pc2;
+uniform vec4 pc3;
+uniform vec4 vc0;
+uniform vec4 vc1;
+uniform vec4 vc2;
+uniform vec4 vc3;
+
+void main()
+{
+    mat4 v = mat4(vc0, vc1, vc2, vc3);
+    mat4 p = mat4(pc0, pc1, pc2, pc3);
+    v_tex_co = tex_co;
+    v_color = particle_color;
+    vec2 world_point = particle_position + (co - vec2(0.5)) * particle_size;
+
+    gl_Position = p * v * vec4(world_point, 0.0, 1.0);
+}
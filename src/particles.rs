@@ -0,0 +1,221 @@
+//! Short-lived specks for exhaust, explosions and sparks. There can be thousands of them at once,
+//! so they're kept in one buffer rather than being entities with rigid bodies, and they're only
+//! for show: nothing in the simulation depends on them.
+
+use std::time::Duration;
+
+use na::Vector2;
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::resources::WorldBounds;
+
+/// Particles past this many aren't created until some of the others have died.
+pub const MAX_PARTICLES: usize = 2048;
+
+/// The spritesheet cell particles are drawn with.
+pub const PARTICLE_SPRITE: usize = 18;
+
+/// How particles look and behave over their lifetime.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct ParticleStyle {
+    pub lifetime: Duration,
+    pub size: f32,
+    /// Color and alpha when a particle is created, and when it dies. It fades from one to the
+    /// other in between.
+    pub colors: [[f32; 4]; 2],
+    /// Fraction of its speed a particle loses every second.
+    pub drag: f32,
+}
+
+impl ParticleStyle {
+    pub const EXHAUST: ParticleStyle = ParticleStyle {
+        lifetime: Duration::from_millis(400),
+        size: 0.4,
+        colors: [[1., 0.8, 0.4, 0.9], [0.8, 0.2, 0.1, 0.]],
+        drag: 2.0,
+    };
+    pub const EXPLOSION: ParticleStyle = ParticleStyle {
+        lifetime: Duration::from_millis(900),
+        size: 0.6,
+        colors: [[1., 0.9, 0.5, 1.], [0.6, 0.1, 0.05, 0.]],
+        drag: 1.5,
+    };
+    pub const SPARKS: ParticleStyle = ParticleStyle {
+        lifetime: Duration::from_millis(250),
+        size: 0.25,
+        colors: [[1., 1., 0.8, 1.], [1., 0.6, 0.2, 0.]],
+        drag: 4.0,
+    };
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Particle {
+    pub position: Vector2<f32>,
+    pub velocity: Vector2<f32>,
+    pub age: Duration,
+    pub style: ParticleStyle,
+}
+
+impl Particle {
+    /// How far through its life the particle is, from 0 to 1.
+    pub fn life(&self) -> f32 {
+        (self.age.as_secs_f32() / self.style.lifetime.as_secs_f32()).min(1.)
+    }
+
+    pub fn color(&self) -> [f32; 4] {
+        let t = self.life();
+        let [mut color, to] = self.style.colors;
+        for (c, to) in color.iter_mut().zip(to.iter()) {
+            *c += (to - *c) * t;
+        }
+        color
+    }
+}
+
+/// Every live particle. Dead ones are swapped out of the buffer, which never grows past
+/// `MAX_PARTICLES`.
+#[derive(Debug)]
+pub struct Particles {
+    particles: Vec<Particle>,
+    // particles don't take anything from the game's generator, so that they can't change how the
+    // game plays out
+    rng: SmallRng,
+}
+
+impl Particles {
+    pub fn len(&self) -> usize {
+        self.particles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.particles.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Particle> {
+        self.particles.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.particles.clear();
+    }
+
+    /// Adds a particle, unless the buffer is full.
+    pub fn spawn(&mut self, position: Vector2<f32>, velocity: Vector2<f32>, style: ParticleStyle) {
+        if self.particles.len() >= MAX_PARTICLES {
+            return;
+        }
+        self.particles.push(Particle {
+            position,
+            velocity,
+            age: Duration::default(),
+            style,
+        });
+    }
+
+    /// Fires a particle off at `speed`, within `spread` radians either side of `direction`, on
+    /// top of the `velocity` of whatever it came from.
+    pub fn emit(
+        &mut self,
+        position: Vector2<f32>,
+        velocity: Vector2<f32>,
+        direction: f32,
+        spread: f32,
+        speed: f32,
+        style: ParticleStyle,
+    ) {
+        let angle = if spread > 0. {
+            direction + self.rng.gen_range(-spread, spread)
+        } else {
+            direction
+        };
+        let speed = speed * self.rng.gen_range(0.5, 1.0);
+        let v = Vector2::new(angle.cos(), angle.sin()) * speed;
+        self.spawn(position, velocity + v, style);
+    }
+
+    /// `count` particles flying out every which way from `position`.
+    pub fn burst(
+        &mut self,
+        position: Vector2<f32>,
+        velocity: Vector2<f32>,
+        count: usize,
+        speed: f32,
+        style: ParticleStyle,
+    ) {
+        for _ in 0..count {
+            self.emit(position, velocity, 0., std::f32::consts::PI, speed, style);
+        }
+    }
+
+    /// Moves everything on by `dt`, wrapping around the world's edges, and lets go of the
+    /// particles that have had their time.
+    pub fn update(&mut self, dt: Duration, bounds: &WorldBounds) {
+        let size = bounds.as_f32();
+        let secs = dt.as_secs_f32();
+        let mut i = 0;
+        while i < self.particles.len() {
+            let p = &mut self.particles[i];
+            p.age += dt;
+            if p.age >= p.style.lifetime {
+                self.particles.swap_remove(i);
+                continue;
+            }
+            p.velocity -= p.velocity * (p.style.drag * secs).min(1.);
+            p.position += p.velocity * secs;
+            p.position.x = p.position.x.rem_euclid(size.x);
+            p.position.y = p.position.y.rem_euclid(size.y);
+            i += 1;
+        }
+    }
+}
+
+impl Default for Particles {
+    fn default() -> Self {
+        Particles {
+            particles: Vec::with_capacity(MAX_PARTICLES),
+            rng: SmallRng::seed_from_u64(0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn particles_fade_out_and_die() {
+        let mut particles = Particles::default();
+        let bounds = WorldBounds::default();
+        particles.spawn(
+            Vector2::new(1., 1.),
+            Vector2::new(-20., 0.),
+            ParticleStyle::SPARKS,
+        );
+
+        particles.update(ParticleStyle::SPARKS.lifetime / 2, &bounds);
+        let p = particles.iter().next().unwrap();
+        // wrapped around the left edge
+        assert!(p.position.x > 90.);
+        assert!(p.color()[3] < ParticleStyle::SPARKS.colors[0][3]);
+
+        particles.update(ParticleStyle::SPARKS.lifetime / 2, &bounds);
+        assert!(particles.is_empty());
+    }
+
+    #[test]
+    fn the_buffer_never_grows() {
+        let mut particles = Particles::default();
+        let capacity = particles.particles.capacity();
+        particles.burst(
+            Vector2::zeros(),
+            Vector2::zeros(),
+            MAX_PARTICLES + 10,
+            1.,
+            ParticleStyle::EXPLOSION,
+        );
+        assert_eq!(particles.len(), MAX_PARTICLES);
+        assert_eq!(particles.particles.capacity(), capacity);
+    }
+}
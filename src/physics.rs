@@ -6,9 +6,10 @@ use rapier2d::dynamics::{IntegrationParameters, JointSet, RigidBodySet};
 use rapier2d::geometry::{BroadPhase, ColliderSet, ContactEvent, NarrowPhase, ProximityEvent};
 use rapier2d::na::Vector2;
 use rapier2d::pipeline::{EventHandler, PhysicsPipeline};
+use serde::{Deserialize, Serialize};
 
-pub use rapier2d::dynamics::{RigidBodyBuilder, RigidBodyHandle};
-pub use rapier2d::geometry::{ColliderBuilder, Proximity};
+pub use rapier2d::dynamics::{RigidBody, RigidBodyBuilder, RigidBodyHandle};
+pub use rapier2d::geometry::{ColliderBuilder, ColliderHandle, Proximity};
 
 use crate::event_queue::{Drain, SharedEventQueue};
 
@@ -41,7 +42,8 @@ impl Default for Physics {
 }
 
 impl Physics {
-    pub fn step(&mut self) {
+    pub fn step(&mut self, dt: f32) {
+        self.integration_parameters.set_dt(dt);
         self.pipeline.step(
             &self.gravity,
             &self.integration_parameters,
@@ -54,6 +56,8 @@ impl Physics {
         )
     }
 
+    /// Removes rigid bodies whose entities no longer exist. Entities should be removed with the
+    /// `Despawn` component, which takes care of their bodies, so this is only a safety net.
     pub fn cleanup(&mut self, world: &mut legion::World) {
         let mut to_remove = vec![];
         for (h, e) in self.event_handler.entity_map.iter() {
@@ -61,20 +65,31 @@ impl Physics {
                 to_remove.push(*h);
             }
         }
+        // the order bodies are removed in decides which handles get reused, so it can't be left to
+        // the map
+        to_remove.sort_by_key(|h| h.into_raw_parts());
 
-        if cfg!(debug) && !to_remove.is_empty() {
+        if cfg!(debug_assertions) && !to_remove.is_empty() {
             debug!("Physics cleanup. Removing {} rigid bodies", to_remove.len());
         }
 
-        for h in to_remove.iter() {
-            self.pipeline.remove_rigid_body(
-                *h,
-                &mut self.broad_phase,
-                &mut self.narrow_phase,
-                &mut self.bodies,
-                &mut self.colliders,
-                &mut self.joints,
-            );
+        for h in to_remove {
+            self.remove(h);
+        }
+    }
+
+    /// Removes a rigid body, along with its colliders, from the physics world.
+    pub fn remove(&mut self, handle: RigidBodyHandle) {
+        self.pipeline.remove_rigid_body(
+            handle,
+            &mut self.broad_phase,
+            &mut self.narrow_phase,
+            &mut self.bodies,
+            &mut self.colliders,
+            &mut self.joints,
+        );
+        if let Some(entity) = self.event_handler.entity_map.remove(&handle) {
+            self.event_handler.collider_map.retain(|_, e| *e != entity);
         }
     }
 
@@ -86,12 +101,40 @@ impl Physics {
     ) -> RigidBodyHandle {
         let h = self.bodies.insert(rbb.build());
         for cb in collider_builders {
-            self.colliders.insert(cb.build(), h, &mut self.bodies);
+            let ch = self.colliders.insert(cb.build(), h, &mut self.bodies);
+            self.event_handler.collider_map.insert(ch, entity);
         }
         self.event_handler.entity_map.insert(h, entity);
         h
     }
 
+    /// Attaches another collider to a rigid body that's already in the physics world. Returns
+    /// `None` if the body isn't there.
+    pub fn add_collider(
+        &mut self,
+        handle: RigidBodyHandle,
+        collider_builder: ColliderBuilder,
+    ) -> Option<ColliderHandle> {
+        let entity = *self.event_handler.entity_map.get(&handle)?;
+        let ch = self
+            .colliders
+            .insert(collider_builder.build(), handle, &mut self.bodies);
+        self.event_handler.collider_map.insert(ch, entity);
+        Some(ch)
+    }
+
+    /// Takes a collider off its rigid body, leaving the body where it is.
+    pub fn remove_collider(&mut self, handle: ColliderHandle) {
+        self.pipeline.remove_collider(
+            handle,
+            &mut self.broad_phase,
+            &mut self.narrow_phase,
+            &mut self.bodies,
+            &mut self.colliders,
+        );
+        self.event_handler.collider_map.remove(&handle);
+    }
+
     // TODO: figure out how to do this without allocating a Vec
     pub fn proximity_events(&mut self) -> Vec<EntityProximityEvent> {
         self.event_handler
@@ -104,6 +147,77 @@ impl Physics {
     pub fn contact_events(&mut self) -> Vec<EntityContactEvent> {
         self.event_handler.contact_queue.get_mut().drain().collect()
     }
+
+    /// Copies the physics world for a snapshot. `index_of` gives each entity's position in the
+    /// snapshot's entity list; bodies of entities missing from it are left unmapped.
+    pub fn snapshot(&self, index_of: &HashMap<Entity, usize>) -> PhysicsSnapshot {
+        PhysicsSnapshot {
+            gravity: self.gravity,
+            broad_phase: self.broad_phase.clone(),
+            narrow_phase: self.narrow_phase.clone(),
+            bodies: self.bodies.clone(),
+            colliders: self.colliders.clone(),
+            joints: self.joints.clone(),
+            body_entities: self
+                .event_handler
+                .entity_map
+                .iter()
+                .filter_map(|(h, e)| index_of.get(e).map(|i| (*h, *i)))
+                .collect(),
+            collider_entities: self
+                .event_handler
+                .collider_map
+                .iter()
+                .filter_map(|(h, e)| index_of.get(e).map(|i| (*h, *i)))
+                .collect(),
+        }
+    }
+
+    /// Rebuilds a physics world from a snapshot, given the entities that were created for the
+    /// snapshot's entity list. Handles are kept as they were, so `RigidBodyHandle` components
+    /// restored alongside still point at the right bodies.
+    pub fn restore(snapshot: PhysicsSnapshot, entities: &[Entity]) -> Physics {
+        let mut physics = Physics {
+            gravity: snapshot.gravity,
+            broad_phase: snapshot.broad_phase,
+            narrow_phase: snapshot.narrow_phase,
+            bodies: snapshot.bodies,
+            colliders: snapshot.colliders,
+            joints: snapshot.joints,
+            ..Physics::default()
+        };
+        for (h, i) in snapshot.body_entities {
+            physics.event_handler.entity_map.insert(h, entities[i]);
+        }
+        for (h, i) in snapshot.collider_entities {
+            physics.event_handler.collider_map.insert(h, entities[i]);
+        }
+        physics
+    }
+}
+
+/// Everything needed to put the physics world back the way it was. Entities are stored as indices
+/// into the snapshot's entity list, since restored entities get new IDs.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PhysicsSnapshot {
+    gravity: Vector2<f32>,
+    broad_phase: BroadPhase,
+    narrow_phase: NarrowPhase,
+    bodies: RigidBodySet,
+    colliders: ColliderSet,
+    joints: JointSet,
+    body_entities: Vec<(RigidBodyHandle, usize)>,
+    collider_entities: Vec<(ColliderHandle, usize)>,
+}
+
+impl PhysicsSnapshot {
+    /// The snapshot entities that bodies and colliders belong to.
+    pub fn entity_indices(&self) -> impl Iterator<Item = usize> + '_ {
+        self.body_entities
+            .iter()
+            .map(|(_, i)| *i)
+            .chain(self.collider_entities.iter().map(|(_, i)| *i))
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -123,42 +237,49 @@ pub struct EntityProximityEvent {
 #[derive(Clone, Default)]
 struct PhysicsEventCollector {
     entity_map: HashMap<RigidBodyHandle, Entity>,
+    // rapier reports events per collider, and a body can have several of them
+    collider_map: HashMap<ColliderHandle, Entity>,
     contact_queue: SharedEventQueue<EntityContactEvent>,
     proximity_queue: SharedEventQueue<EntityProximityEvent>,
 }
 
+impl PhysicsEventCollector {
+    // Events can still arrive for colliders whose entity was removed during the same step, so
+    // those are dropped rather than treated as a bug.
+    fn entities(&self, h1: ColliderHandle, h2: ColliderHandle) -> Option<(Entity, Entity)> {
+        match (self.collider_map.get(&h1), self.collider_map.get(&h2)) {
+            (Some(e1), Some(e2)) => Some((*e1, *e2)),
+            _ => {
+                debug!("Ignoring event for unknown colliders {:?}, {:?}", h1, h2);
+                None
+            }
+        }
+    }
+}
+
 impl EventHandler for PhysicsEventCollector {
     fn handle_contact_event(&self, e: ContactEvent) {
         match e {
             ContactEvent::Started(h1, h2) => {
-                let contact_entity_1 = self.entity_map.get(&h1).unwrap();
-                let contact_entity_2 = self.entity_map.get(&h2).unwrap();
-
-                self.contact_queue.push(EntityContactEvent::Started(
-                    *contact_entity_1,
-                    *contact_entity_2,
-                ));
+                if let Some((e1, e2)) = self.entities(h1, h2) {
+                    self.contact_queue.push(EntityContactEvent::Started(e1, e2));
+                }
             }
             ContactEvent::Stopped(h1, h2) => {
-                let contact_entity_1 = self.entity_map.get(&h1).unwrap();
-                let contact_entity_2 = self.entity_map.get(&h2).unwrap();
-
-                self.contact_queue.push(EntityContactEvent::Stopped(
-                    *contact_entity_1,
-                    *contact_entity_2,
-                ));
+                if let Some((e1, e2)) = self.entities(h1, h2) {
+                    self.contact_queue.push(EntityContactEvent::Stopped(e1, e2));
+                }
             }
         }
     }
     fn handle_proximity_event(&self, e: ProximityEvent) {
-        let collider_1 = self.entity_map.get(&e.collider1).unwrap();
-        let collider_2 = self.entity_map.get(&e.collider2).unwrap();
-
-        self.proximity_queue.push(EntityProximityEvent {
-            e1: *collider_1,
-            e2: *collider_2,
-            prev_status: e.prev_status,
-            new_status: e.new_status,
-        });
+        if let Some((e1, e2)) = self.entities(e.collider1, e.collider2) {
+            self.proximity_queue.push(EntityProximityEvent {
+                e1,
+                e2,
+                prev_status: e.prev_status,
+                new_status: e.new_status,
+            });
+        }
     }
 }
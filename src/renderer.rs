@@ -1,5 +1,5 @@
 #[cfg(not(target_arch = "wasm32"))]
-use glfw::{Context as _, WindowEvent};
+use glfw::{Context as _, JoystickId, WindowEvent};
 use legion::*;
 use luminance_derive::{Semantics, UniformInterface, Vertex};
 use luminance_front::blending::{Blending, Equation, Factor};
@@ -15,10 +15,13 @@ use luminance_glfw::GlfwSurface;
 #[cfg(target_arch = "wasm32")]
 use luminance_web_sys::WebSysWebGL2Surface;
 use luminance_windowing::{WindowDim, WindowOpt};
-use nalgebra::{Matrix4, Vector3, Vector4};
+use nalgebra::{Matrix4, Vector2, Vector3, Vector4};
 
-use crate::components::{Player, Sprite, Transform};
-use crate::constants::SPRITES_PER_HALF_SCREEN;
+use crate::components::{CompositeSprite, Player, Sprite, Transform};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::input::GamepadState;
+use crate::overlay::{self, Overlay, ScreenSprite};
+use crate::particles::{Particles, PARTICLE_SPRITE};
 use crate::physics::RigidBodyHandle;
 use crate::resources::WindowDimensions;
 use crate::resources::WorldBounds;
@@ -31,6 +34,9 @@ const SPRITE_FS: &str = include_str!("texture-fs.glsl");
 const DEFAULT_VS: &str = include_str!("vs.glsl");
 const DEFAULT_FS: &str = include_str!("fs.glsl");
 
+const PARTICLE_VS: &str = include_str!("particle-vs.glsl");
+const PARTICLE_FS: &str = include_str!("particle-fs.glsl");
+
 const SPRITESHEET: &[u8] = include_bytes!("spritesheet.png");
 
 #[cfg(target_arch = "wasm32")]
@@ -86,13 +92,23 @@ const INSTANCES: [Instance; 9] = [
     },
 ];
 
+// things on screen don't wrap around the world, so they only need drawing once
+const SCREEN_INSTANCES: [Instance; 1] = [Instance {
+    offset: VertexInstancePosition::new([0., 0.]),
+}];
+
 pub struct Renderer {
     surface: RenderSurface,
     default_shader: Program<Semantics, (), DefaultShaderInterface>,
     sprite_shader: Program<Semantics, (), SpriteShaderInterface>,
+    particle_shader: Program<Semantics, (), ParticleShaderInterface>,
     projection: Matrix4<f32>,
     spritesheet: Spritesheet,
     tesses: Vec<Tess<Vertex, (), Instance>>,
+    screen_tesses: Vec<Tess<Vertex, (), Instance>>,
+    font: Spritesheet,
+    font_tesses: Vec<Tess<Vertex, (), Instance>>,
+    particle_vertices: Vec<Vertex>,
 }
 
 impl Renderer {
@@ -103,7 +119,8 @@ impl Renderer {
         let [w, h] = tex.size();
         let spritesheet = Spritesheet::new(tex, w, h, 32);
         let mut tesses = vec![];
-        for i in 0..(w * h / 32) {
+        let mut screen_tesses = vec![];
+        for i in 0..spritesheet.sprite_count() {
             let tess = surface
                 .new_tess()
                 .set_vertices(
@@ -121,16 +138,27 @@ impl Renderer {
                 .build()
                 .unwrap();
             tesses.push(tess);
+            screen_tesses.push(screen_tess(&mut surface, &spritesheet, i));
         }
 
-        let projection = Matrix4::new_orthographic(
-            -SPRITES_PER_HALF_SCREEN,
-            SPRITES_PER_HALF_SCREEN,
-            -SPRITES_PER_HALF_SCREEN / dims.aspect_ratio,
-            SPRITES_PER_HALF_SCREEN / dims.aspect_ratio,
-            -1.,
-            1.,
-        );
+        let particle_vertices = spritesheet
+            .get_vertices(PARTICLE_SPRITE as u32)
+            .iter()
+            .map(|d| Vertex {
+                pos: VertexPosition::new(d.0),
+                tex_coords: TexturePosition::new(d.1),
+            })
+            .collect();
+
+        let img = read_image(overlay::FONT).expect("Failed to load font");
+        let tex = load_texture(&mut surface, img);
+        let [w, h] = tex.size();
+        let font = Spritesheet::new(tex, w, h, overlay::GLYPH_CELL);
+        let font_tesses = (0..font.sprite_count())
+            .map(|i| screen_tess(&mut surface, &font, i))
+            .collect();
+
+        let projection = dims.projection();
         let default_shader = surface
             .new_shader_program::<Semantics, (), DefaultShaderInterface>()
             .from_strings(DEFAULT_VS, None, None, DEFAULT_FS)
@@ -141,13 +169,23 @@ impl Renderer {
             .from_strings(SPRITE_VS, None, None, SPRITE_FS)
             .expect("Shader program creation")
             .ignore_warnings();
+        let particle_shader = surface
+            .new_shader_program::<Semantics, (), ParticleShaderInterface>()
+            .from_strings(PARTICLE_VS, None, None, PARTICLE_FS)
+            .expect("Shader program creation")
+            .ignore_warnings();
         Renderer {
             surface,
             default_shader,
             sprite_shader,
+            particle_shader,
             projection,
             spritesheet,
             tesses,
+            screen_tesses,
+            font,
+            font_tesses,
+            particle_vertices,
         }
     }
 
@@ -160,12 +198,62 @@ impl Renderer {
                 dst: Factor::SrcAlphaComplement,
             })
             .set_depth_test(None);
+        // particles add up to something brighter where they overlap
+        let particle_st = RenderState::default()
+            .set_blending(Blending {
+                equation: Equation::Additive,
+                src: Factor::SrcAlpha,
+                dst: Factor::One,
+            })
+            .set_depth_test(None);
 
         let sprite_program = &mut self.sprite_shader;
         let default_program = &mut self.default_shader;
+        let particle_program = &mut self.particle_shader;
         let tex = &mut self.spritesheet.texture;
+        let font_tex = &mut self.font.texture;
+        let font_tesses = &self.font_tesses;
         let projection = self.projection;
         let tesses = &self.tesses;
+        let screen_tesses = &self.screen_tesses;
+        let dims = *resources.get::<WindowDimensions>().unwrap();
+        let screen_projection = dims.screen_projection();
+        let overlay = Overlay::gather(world, resources);
+        let glyphs: Vec<_> = overlay
+            .texts
+            .iter()
+            .flat_map(|text| text.glyphs())
+            .collect();
+        let view = resources.get::<ViewMatrix>().unwrap().0;
+        let particle_tess = {
+            let particles = resources.get::<Particles>().unwrap();
+            let bounds = resources.get::<WorldBounds>().unwrap();
+            // the view follows the player, so draw each particle wherever it's closest to them
+            let center = -Vector2::new(view[(0, 3)], view[(1, 3)]);
+            let instances: Vec<_> = particles
+                .iter()
+                .map(|p| ParticleInstance {
+                    position: ParticlePosition::new(
+                        (center + bounds.shortest_offset(center, p.position)).into(),
+                    ),
+                    color: ParticleColor::new(p.color()),
+                    size: ParticleSize::new(p.style.size),
+                })
+                .collect();
+            if instances.is_empty() {
+                None
+            } else {
+                Some(
+                    self.surface
+                        .new_tess()
+                        .set_vertices(&self.particle_vertices[..])
+                        .set_instances(instances)
+                        .set_mode(Mode::TriangleFan)
+                        .build()
+                        .unwrap(),
+                )
+            }
+        };
         let collider_tess = self
             .surface
             .new_tess()
@@ -182,7 +270,7 @@ impl Renderer {
                 &PipelineState::default(),
                 |pipeline, mut shading_gate| {
                     let bound_tex = pipeline.bind_texture(tex)?;
-                    let view = resources.get::<ViewMatrix>().unwrap().0;
+                    let bound_font = pipeline.bind_texture(font_tex)?;
 
                     let bounds = resources.get::<WorldBounds>().unwrap();
 
@@ -215,8 +303,48 @@ impl Renderer {
                             })?
                         }
 
+                        let mut composite_query = <(&CompositeSprite, &Transform)>::query();
+                        for (sprite, transform) in composite_query.iter(world) {
+                            for part in sprite.parts.iter().filter(|p| p.visible) {
+                                let mut model = transform.get_matrix();
+                                model *= Matrix4::new_translation(&Vector3::new(
+                                    part.offset.x - 0.5,
+                                    part.offset.y - 0.5,
+                                    0.,
+                                ));
+                                iface.set(&uni.model, model.into());
+                                iface.set(&uni.mc0, model.column(0).into());
+                                iface.set(&uni.mc1, model.column(1).into());
+                                iface.set(&uni.mc2, model.column(2).into());
+                                iface.set(&uni.mc3, model.column(3).into());
+                                iface.set(&uni.sprite_color, part.color);
+                                render_gate.render(&render_st, |mut tess_gate| {
+                                    tess_gate.render(&tesses[part.index])
+                                })?
+                            }
+                        }
+
                         Ok(())
                     })?;
+                    if let Some(particle_tess) = &particle_tess {
+                        shading_gate.shade(
+                            particle_program,
+                            |mut iface, uni, mut render_gate| {
+                                iface.set(&uni.image, bound_tex.binding());
+                                iface.set(&uni.pc0, projection.column(0).into());
+                                iface.set(&uni.pc1, projection.column(1).into());
+                                iface.set(&uni.pc2, projection.column(2).into());
+                                iface.set(&uni.pc3, projection.column(3).into());
+                                iface.set(&uni.vc0, view.column(0).into());
+                                iface.set(&uni.vc1, view.column(1).into());
+                                iface.set(&uni.vc2, view.column(2).into());
+                                iface.set(&uni.vc3, view.column(3).into());
+                                render_gate.render(&particle_st, |mut tess_gate| {
+                                    tess_gate.render(particle_tess)
+                                })
+                            },
+                        )?;
+                    }
                     shading_gate.shade(default_program, |mut iface, uni, mut render_gate| {
                         iface.set(&uni.projection, projection.into());
                         iface.set(&uni.pc0, projection.column(0).into());
@@ -243,6 +371,50 @@ impl Renderer {
                             })?
                         }
                         Ok(())
+                    })?;
+                    // drawn last, so it's over everything in the world
+                    shading_gate.shade(sprite_program, |mut iface, uni, mut render_gate| {
+                        let view = Matrix4::<f32>::identity();
+                        iface.set(&uni.image, bound_tex.binding());
+                        iface.set(&uni.projection, screen_projection.into());
+                        iface.set(&uni.pc0, screen_projection.column(0).into());
+                        iface.set(&uni.pc1, screen_projection.column(1).into());
+                        iface.set(&uni.pc2, screen_projection.column(2).into());
+                        iface.set(&uni.pc3, screen_projection.column(3).into());
+                        iface.set(&uni.view, view.into());
+                        iface.set(&uni.vc0, view.column(0).into());
+                        iface.set(&uni.vc1, view.column(1).into());
+                        iface.set(&uni.vc2, view.column(2).into());
+                        iface.set(&uni.vc3, view.column(3).into());
+                        iface.set(&uni.world_bounds, [0., 0.]);
+
+                        for sprite in &overlay.sprites {
+                            let model = screen_model(sprite);
+                            iface.set(&uni.model, model.into());
+                            iface.set(&uni.mc0, model.column(0).into());
+                            iface.set(&uni.mc1, model.column(1).into());
+                            iface.set(&uni.mc2, model.column(2).into());
+                            iface.set(&uni.mc3, model.column(3).into());
+                            iface.set(&uni.sprite_color, sprite.color);
+                            render_gate.render(&render_st, |mut tess_gate| {
+                                tess_gate.render(&screen_tesses[sprite.index])
+                            })?
+                        }
+
+                        iface.set(&uni.image, bound_font.binding());
+                        for glyph in &glyphs {
+                            let model = screen_model(glyph);
+                            iface.set(&uni.model, model.into());
+                            iface.set(&uni.mc0, model.column(0).into());
+                            iface.set(&uni.mc1, model.column(1).into());
+                            iface.set(&uni.mc2, model.column(2).into());
+                            iface.set(&uni.mc3, model.column(3).into());
+                            iface.set(&uni.sprite_color, glyph.color);
+                            render_gate.render(&render_st, |mut tess_gate| {
+                                tess_gate.render(&font_tesses[glyph.index])
+                            })?
+                        }
+                        Ok(())
                     })
                 },
             )
@@ -258,6 +430,13 @@ impl Renderer {
         self.surface.window.glfw.poll_events();
         self.surface.events_rx.try_iter()
     }
+
+    /// What the first joystick is doing, if it's a gamepad GLFW knows the layout of.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn gamepad_state(&self) -> Option<GamepadState> {
+        let joystick = self.surface.window.glfw.get_joystick(JoystickId::Joystick1);
+        joystick.get_gamepad_state().map(|state| (&state).into())
+    }
 }
 
 impl Default for Renderer {
@@ -266,6 +445,38 @@ impl Default for Renderer {
     }
 }
 
+/// A quad for one cell of `sheet`, drawn once rather than wrapped around the world.
+fn screen_tess(
+    surface: &mut RenderSurface,
+    sheet: &Spritesheet,
+    index: u32,
+) -> Tess<Vertex, (), Instance> {
+    surface
+        .new_tess()
+        .set_vertices(
+            sheet
+                .get_vertices(index)
+                .iter()
+                .map(|d| Vertex {
+                    pos: VertexPosition::new(d.0),
+                    tex_coords: TexturePosition::new(d.1),
+                })
+                .collect::<Vec<Vertex>>(),
+        )
+        .set_instances(&SCREEN_INSTANCES[..])
+        .set_mode(Mode::TriangleFan)
+        .build()
+        .unwrap()
+}
+
+/// Places a unit quad over `sprite` on screen. The screen's y axis points down, so the quad is
+/// flipped to keep the sprite upright.
+fn screen_model(sprite: &ScreenSprite) -> Matrix4<f32> {
+    let half = sprite.size / 2.;
+    Matrix4::new_translation(&Vector3::new(sprite.x - half, sprite.y + half, 0.))
+        * Matrix4::new_nonuniform_scaling(&Vector3::new(sprite.size, -sprite.size, 1.))
+}
+
 #[cfg(target_arch = "wasm32")]
 fn swap_buffers(_: &mut RenderSurface) {}
 #[cfg(not(target_arch = "wasm32"))]
@@ -395,6 +606,28 @@ struct SpriteShaderInterface {
     world_bounds: Uniform<[f32; 2]>,
 }
 
+#[derive(UniformInterface)]
+struct ParticleShaderInterface {
+    #[uniform(unbound)]
+    pc0: Uniform<[f32; 4]>,
+    #[uniform(unbound)]
+    pc1: Uniform<[f32; 4]>,
+    #[uniform(unbound)]
+    pc2: Uniform<[f32; 4]>,
+    #[uniform(unbound)]
+    pc3: Uniform<[f32; 4]>,
+    #[uniform(unbound)]
+    vc0: Uniform<[f32; 4]>,
+    #[uniform(unbound)]
+    vc1: Uniform<[f32; 4]>,
+    #[uniform(unbound)]
+    vc2: Uniform<[f32; 4]>,
+    #[uniform(unbound)]
+    vc3: Uniform<[f32; 4]>,
+    #[uniform(unbound)]
+    image: Uniform<TextureBinding<Dim2, NormUnsigned>>,
+}
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Semantics)]
 pub enum Semantics {
     #[sem(name = "co", repr = "[f32; 2]", wrapper = "VertexPosition")]
@@ -407,6 +640,16 @@ pub enum Semantics {
         wrapper = "VertexInstancePosition"
     )]
     InstancePosition,
+    #[sem(
+        name = "particle_position",
+        repr = "[f32; 2]",
+        wrapper = "ParticlePosition"
+    )]
+    ParticlePosition,
+    #[sem(name = "particle_color", repr = "[f32; 4]", wrapper = "ParticleColor")]
+    ParticleColor,
+    #[sem(name = "particle_size", repr = "f32", wrapper = "ParticleSize")]
+    ParticleSize,
 }
 
 #[repr(C)]
@@ -424,6 +667,16 @@ pub struct Instance {
     pub offset: VertexInstancePosition,
 }
 
+/// One particle, drawn as a copy of the particle sprite's quad.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Vertex)]
+#[vertex(sem = "Semantics", instanced = "true")]
+struct ParticleInstance {
+    position: ParticlePosition,
+    color: ParticleColor,
+    size: ParticleSize,
+}
+
 #[repr(C)]
 #[derive(Clone, Copy, Debug, PartialEq, Vertex)]
 #[vertex(sem = "Semantics")]
@@ -0,0 +1,229 @@
+//! Recording a game's input so it can be played back exactly. The simulation runs in fixed steps
+//! and takes all of its randomness from a seeded generator, so the same level, seed and input on
+//! the same frames always give the same game.
+
+use std::fmt;
+
+use legion::*;
+use serde::{Deserialize, Serialize};
+
+use crate::components::{Health, Transform};
+use crate::input::{InputEvent, InputMap};
+use crate::level::Level;
+use crate::resources::{Session, Time};
+
+/// Bumped whenever the replay format changes, or the simulation changes in a way that would make
+/// old replays play out differently.
+pub const REPLAY_VERSION: u32 = 1;
+
+/// What a replay needs to start from the same place as the recorded game.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReplayHeader {
+    pub version: u32,
+    pub seed: u64,
+    pub level: Level,
+    /// Events are recorded as keys and buttons, so playing them back needs the same bindings.
+    pub input_map: InputMap,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Replay {
+    pub header: ReplayHeader,
+    /// Every input event, with the frame it was handled on, in order.
+    pub events: Vec<(u64, InputEvent)>,
+    /// `world_hash` after every recorded frame, to catch playback going its own way.
+    pub hashes: Vec<u64>,
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Encoding(bincode::Error),
+    /// The replay was recorded by a different version of the game.
+    Version {
+        found: u32,
+        expected: u32,
+    },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayError::Encoding(e) => write!(f, "couldn't read replay: {}", e),
+            ReplayError::Version { found, expected } => write!(
+                f,
+                "replay is version {}, but this game plays version {}",
+                found, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl From<bincode::Error> for ReplayError {
+    fn from(e: bincode::Error) -> Self {
+        ReplayError::Encoding(e)
+    }
+}
+
+impl Replay {
+    pub fn new(seed: u64, level: Level, input_map: InputMap) -> Self {
+        Replay {
+            header: ReplayHeader {
+                version: REPLAY_VERSION,
+                seed,
+                level,
+                input_map,
+            },
+            events: vec![],
+            hashes: vec![],
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("replays should always serialize")
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Replay, ReplayError> {
+        // the version comes first, and a different version might not even deserialize
+        let version: u32 = bincode::deserialize(bytes)?;
+        if version != REPLAY_VERSION {
+            return Err(ReplayError::Version {
+                found: version,
+                expected: REPLAY_VERSION,
+            });
+        }
+        Ok(bincode::deserialize(bytes)?)
+    }
+}
+
+/// Feeds a replay's events back in on the frames they were recorded on.
+#[derive(Debug)]
+pub struct Playback {
+    replay: Replay,
+    next_event: usize,
+    diverged_at: Option<u64>,
+}
+
+impl Playback {
+    pub fn new(replay: Replay) -> Self {
+        Playback {
+            replay,
+            next_event: 0,
+            diverged_at: None,
+        }
+    }
+
+    /// The events recorded for `frame`.
+    pub fn events_for(&mut self, frame: u64) -> Vec<InputEvent> {
+        let mut events = vec![];
+        while let Some((f, e)) = self.replay.events.get(self.next_event) {
+            if *f > frame {
+                break;
+            }
+            events.push(e.clone());
+            self.next_event += 1;
+        }
+        events
+    }
+
+    /// Compares the world after `frame` with the recording, remembering the first frame where
+    /// they differ.
+    pub fn check(&mut self, frame: u64, hash: u64) {
+        if self.diverged_at.is_some() {
+            return;
+        }
+        match self.replay.hashes.get(frame as usize) {
+            Some(expected) if *expected != hash => {
+                error!("Replay diverged from the recording on frame {}", frame);
+                self.diverged_at = Some(frame);
+            }
+            _ => {}
+        }
+    }
+
+    /// The first frame that played out differently from the recording, if any has.
+    pub fn diverged_at(&self) -> Option<u64> {
+        self.diverged_at
+    }
+
+    /// Whether every recorded frame has been played.
+    pub fn is_finished(&self, frame: u64) -> bool {
+        frame as usize >= self.replay.hashes.len()
+    }
+}
+
+/// 64-bit FNV-1a. Recordings are checked against hashes from whichever build of the game made
+/// them, and std's hashers can change from one Rust release to the next.
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    fn new() -> Self {
+        Fnv1a(Self::OFFSET_BASIS)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= u64::from(*b);
+            self.0 = self.0.wrapping_mul(Self::PRIME);
+        }
+    }
+}
+
+/// A fingerprint of the simulation: where everything is, how healthy it is, and the score. Two
+/// runs that hash the same on every frame played out the same way.
+pub fn world_hash(world: &World, resources: &Resources) -> u64 {
+    let hash_of = |f: &dyn Fn(&mut Fnv1a)| {
+        let mut hasher = Fnv1a::new();
+        f(&mut hasher);
+        hasher.0
+    };
+
+    // entities are combined so that the order queries visit them in doesn't matter
+    let mut hash = 0u64;
+    for (t, health) in <(&Transform, Option<&Health>)>::query().iter(world) {
+        hash = hash.wrapping_add(hash_of(&|h| {
+            for x in t.isometry.translation.vector.iter() {
+                h.write(&x.to_bits().to_le_bytes());
+            }
+            for x in t.isometry.rotation.coords.iter() {
+                h.write(&x.to_bits().to_le_bytes());
+            }
+            match health {
+                Some(health) => {
+                    h.write(&[1]);
+                    h.write(&health.0.to_le_bytes());
+                }
+                None => h.write(&[0]),
+            }
+        }));
+    }
+
+    let time = resources.get::<Time>().unwrap();
+    let session = resources.get::<Session>().unwrap();
+    hash ^ hash_of(&|h| {
+        h.write(&time.frame.to_le_bytes());
+        h.write(&session.score.to_le_bytes());
+        h.write(&session.lives.to_le_bytes());
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fnv1a_matches_the_reference_hashes() {
+        let hash = |bytes: &[u8]| {
+            let mut hasher = Fnv1a::new();
+            hasher.write(bytes);
+            hasher.0
+        };
+        assert_eq!(hash(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(hash(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(hash(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+}
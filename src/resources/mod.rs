@@ -1,12 +1,39 @@
-use na::Vector2;
+use na::{Matrix4, Vector2};
+use rand::SeedableRng;
+use rand_pcg::Pcg64Mcg;
+use serde::{Deserialize, Serialize};
 use std::borrow::BorrowMut;
+use std::time::Duration;
 
+use crate::constants::{FIXED_TIMESTEP, SPRITES_PER_HALF_SCREEN};
+
+mod session;
+mod waves;
+
+pub use session::*;
+pub use waves::*;
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct WorldBounds(pub Vector2<u32>);
 
 impl WorldBounds {
     pub fn as_f32(&self) -> Vector2<f32> {
         Vector2::new(self.0.x as f32, self.0.y as f32)
     }
+
+    /// The shortest way to get from `from` to `to`, which might be across the world's edge.
+    pub fn shortest_offset(&self, from: Vector2<f32>, to: Vector2<f32>) -> Vector2<f32> {
+        let bounds = self.as_f32();
+        let mut offset = to - from;
+        for i in 0..2 {
+            if offset[i] > bounds[i] / 2.0 {
+                offset[i] -= bounds[i];
+            } else if offset[i] < -bounds[i] / 2.0 {
+                offset[i] += bounds[i];
+            }
+        }
+        offset
+    }
 }
 
 impl Default for WorldBounds {
@@ -22,6 +49,27 @@ pub struct WindowDimensions {
     pub h: u32,
 }
 
+impl WindowDimensions {
+    /// Maps view space onto the window, which is always `SPRITES_PER_HALF_SCREEN * 2` sprites
+    /// across.
+    pub fn projection(&self) -> Matrix4<f32> {
+        Matrix4::new_orthographic(
+            -SPRITES_PER_HALF_SCREEN,
+            SPRITES_PER_HALF_SCREEN,
+            -SPRITES_PER_HALF_SCREEN / self.aspect_ratio,
+            SPRITES_PER_HALF_SCREEN / self.aspect_ratio,
+            -1.,
+            1.,
+        )
+    }
+
+    /// Maps pixels from the top left of the window onto it, for drawing things that stay put on
+    /// screen.
+    pub fn screen_projection(&self) -> Matrix4<f32> {
+        Matrix4::new_orthographic(0., self.w as f32, self.h as f32, 0., -1., 1.)
+    }
+}
+
 impl Default for WindowDimensions {
     fn default() -> Self {
         WindowDimensions {
@@ -31,3 +79,76 @@ impl Default for WindowDimensions {
         }
     }
 }
+
+/// Simulation clock, advanced once per fixed step by `Game`.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct Time {
+    /// Length of a simulation step. This is always the fixed timestep; `time_scale` changes how
+    /// many steps run per frame instead.
+    pub delta: Duration,
+    /// Simulated time since the game started.
+    pub elapsed: Duration,
+    /// Number of simulation steps run so far.
+    pub frame: u64,
+    pub time_scale: f32,
+    /// Wall-clock time between the last two rendered frames.
+    pub frame_delta: Duration,
+}
+
+impl Time {
+    pub fn delta_seconds(&self) -> f32 {
+        self.delta.as_secs_f32()
+    }
+}
+
+impl Default for Time {
+    fn default() -> Self {
+        Time {
+            delta: FIXED_TIMESTEP,
+            elapsed: Duration::default(),
+            frame: 0,
+            time_scale: 1.0,
+            frame_delta: Duration::default(),
+        }
+    }
+}
+
+/// Random number generator shared by the systems. Everything random in the simulation should come
+/// from here so a run can be reproduced from its seed. It's saved along with the rest of a
+/// snapshot, so a restored game draws the same numbers the original would have.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Random(pub Pcg64Mcg);
+
+impl Random {
+    pub fn seeded(seed: u64) -> Self {
+        Random(Pcg64Mcg::seed_from_u64(seed))
+    }
+}
+
+impl Default for Random {
+    fn default() -> Self {
+        Random::seeded(rand::random())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn shortest_offset_wraps_around() {
+        let bounds = WorldBounds::default();
+        assert_eq!(
+            bounds.shortest_offset(Vector2::new(10., 10.), Vector2::new(20., 15.)),
+            Vector2::new(10., 5.)
+        );
+        assert_eq!(
+            bounds.shortest_offset(Vector2::new(95., 45.), Vector2::new(5., 5.)),
+            Vector2::new(10., 10.)
+        );
+        assert_eq!(
+            bounds.shortest_offset(Vector2::new(5., 5.), Vector2::new(95., 45.)),
+            Vector2::new(-10., -10.)
+        );
+    }
+}
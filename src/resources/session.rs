@@ -0,0 +1,138 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::components::EntityTag;
+
+/// How long the wreck of the player's ship is left on screen before the next one comes in.
+pub const RESPAWN_DELAY: Duration = Duration::from_secs(2);
+/// How long a freshly respawned ship can't be hurt.
+pub const INVULNERABILITY: Duration = Duration::from_secs(3);
+pub const STARTING_LIVES: u32 = 3;
+
+// points for destroying things, by tag. The first matching entry wins.
+const SCORE_TABLE: [(EntityTag, u32); 3] = [
+    (EntityTag::BOSS, 15000),
+    (EntityTag::ENEMY, 150),
+    (EntityTag::ASTEROID, 5),
+];
+
+/// Where the game session is at. `Game` picks which systems to run from this.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum GameState {
+    /// Waiting for someone to start a game.
+    Attract,
+    Playing,
+    /// The player's ship was destroyed. The next one comes in at `respawn_at`, if there are any
+    /// lives left.
+    PlayerDead {
+        respawn_at: Duration,
+    },
+    /// A new ship should be placed somewhere safe.
+    Respawning,
+    /// Only a game that's `Playing` can be paused, and it goes back to `Playing` when unpaused.
+    Paused,
+    GameOver,
+    /// A new game was asked for after a game over. `Game` starts the level over at the end of the
+    /// step, and then it's `Playing`.
+    Starting,
+}
+
+impl GameState {
+    /// The state after `self` once the simulation clock reaches `now`.
+    pub fn advance(self, now: Duration, lives: u32) -> GameState {
+        match self {
+            GameState::PlayerDead { respawn_at } if now >= respawn_at => {
+                if lives > 0 {
+                    GameState::Respawning
+                } else {
+                    GameState::GameOver
+                }
+            }
+            state => state,
+        }
+    }
+
+    /// Whether the simulation should be running.
+    pub fn in_game(self) -> bool {
+        match self {
+            GameState::Attract | GameState::Paused | GameState::GameOver | GameState::Starting => {
+                false
+            }
+            _ => true,
+        }
+    }
+}
+
+impl Default for GameState {
+    fn default() -> Self {
+        GameState::Attract
+    }
+}
+
+/// Score and lives for the current game.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct Session {
+    pub score: u32,
+    /// Ships left, including the one in play.
+    pub lives: u32,
+}
+
+impl Session {
+    /// What destroying something tagged `tag` is worth.
+    pub fn points(tag: EntityTag) -> u32 {
+        SCORE_TABLE
+            .iter()
+            .find(|(t, _)| tag.contains(*t))
+            .map_or(0, |(_, points)| *points)
+    }
+
+    /// Loses a ship, returning what the game should do next.
+    pub fn lose_life(&mut self, now: Duration) -> GameState {
+        self.lives = self.lives.saturating_sub(1);
+        GameState::PlayerDead {
+            respawn_at: now + RESPAWN_DELAY,
+        }
+    }
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Session {
+            score: 0,
+            lives: STARTING_LIVES,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn respawns_until_out_of_lives() {
+        let mut session = Session::default();
+        let now = Duration::from_secs(10);
+
+        let state = session.lose_life(now);
+        assert_eq!(state.advance(now, session.lives), state);
+        assert_eq!(
+            state.advance(now + RESPAWN_DELAY, session.lives),
+            GameState::Respawning
+        );
+
+        session.lives = 1;
+        let state = session.lose_life(now);
+        assert_eq!(
+            state.advance(now + RESPAWN_DELAY, session.lives),
+            GameState::GameOver
+        );
+    }
+
+    #[test]
+    fn scores_by_tag() {
+        assert_eq!(Session::points(EntityTag::ASTEROID), 5);
+        assert_eq!(Session::points(EntityTag::BOSS), 15000);
+        assert_eq!(Session::points(EntityTag::PLAYER), 0);
+    }
+}
@@ -0,0 +1,134 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// Breather between the last enemy of a wave going down and the next wave coming in.
+pub const WAVE_DELAY: Duration = Duration::from_secs(3);
+pub const WAVES_PER_ZONE: u32 = 3;
+
+/// The parts of space the player flies through, in order. After the void it starts over from the
+/// worker zone, only harder.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Zone {
+    Worker,
+    Warrior,
+    Planetoid,
+    Void,
+}
+
+const ZONES: [Zone; 4] = [Zone::Worker, Zone::Warrior, Zone::Planetoid, Zone::Void];
+
+/// What to spawn for a wave. Asteroids and planetoids are topped up to these counts rather than
+/// added on top of whatever is still floating around.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct Wave {
+    pub asteroids: u32,
+    pub planetoids: u32,
+    pub workers: u32,
+    pub warriors: u32,
+}
+
+/// Multipliers the enemy AI applies to its base numbers.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Difficulty {
+    pub speed: f32,
+    pub fire_rate: f32,
+}
+
+/// Decides what each wave brings, and keeps track of which one the player is on.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct WaveDirector {
+    /// Starts at 1.
+    pub wave: u32,
+    /// When the next wave comes in, or `None` while one is being fought.
+    pub next_wave_at: Option<Duration>,
+}
+
+impl WaveDirector {
+    /// How many times the player has been all the way through the zones.
+    fn lap(&self) -> u32 {
+        (self.wave - 1) / (WAVES_PER_ZONE * ZONES.len() as u32)
+    }
+
+    pub fn zone(&self) -> Zone {
+        ZONES[((self.wave - 1) / WAVES_PER_ZONE) as usize % ZONES.len()]
+    }
+
+    pub fn plan(&self) -> Wave {
+        // each wave in a zone brings a bit more than the last, and each lap a bit more again
+        let n = (self.wave - 1) % WAVES_PER_ZONE;
+        let lap = self.lap();
+        match self.zone() {
+            Zone::Worker => Wave {
+                asteroids: 4,
+                planetoids: 0,
+                workers: 3 + n + lap,
+                warriors: 1 + lap,
+            },
+            Zone::Warrior => Wave {
+                asteroids: 4,
+                planetoids: 0,
+                workers: 2,
+                warriors: 3 + n + lap,
+            },
+            Zone::Planetoid => Wave {
+                asteroids: 2,
+                planetoids: 2 + lap,
+                workers: 2 + n,
+                warriors: 1 + n / 2 + lap,
+            },
+            Zone::Void => Wave {
+                asteroids: 0,
+                planetoids: 0,
+                workers: 1,
+                warriors: 4 + n + lap,
+            },
+        }
+    }
+
+    pub fn difficulty(&self) -> Difficulty {
+        let step = (self.wave - 1) as f32;
+        Difficulty {
+            speed: (1.0 + step * 0.05).min(2.0),
+            fire_rate: (1.0 + step * 0.1).min(3.0),
+        }
+    }
+
+    /// Moves on to the next wave, which comes in after a short break.
+    pub fn complete(&mut self, now: Duration) {
+        self.wave += 1;
+        self.next_wave_at = Some(now + WAVE_DELAY);
+    }
+}
+
+impl Default for WaveDirector {
+    fn default() -> Self {
+        WaveDirector {
+            wave: 1,
+            // the first wave comes in as soon as the game starts
+            next_wave_at: Some(Duration::default()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn zones_cycle_and_get_harder() {
+        let mut director = WaveDirector::default();
+        assert_eq!(director.zone(), Zone::Worker);
+        let first = director.plan();
+
+        for _ in 0..WAVES_PER_ZONE {
+            director.complete(Duration::default());
+        }
+        assert_eq!(director.zone(), Zone::Warrior);
+
+        director.wave += WAVES_PER_ZONE * 3;
+        assert_eq!(director.zone(), Zone::Worker);
+        assert!(director.plan().workers > first.workers);
+        assert!(director.difficulty().speed > 1.0);
+    }
+}
@@ -0,0 +1,220 @@
+//! Saving and loading the whole simulation: every entity's components, the resources systems
+//! depend on, and the physics world.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use legion::world::{Entry, EntryRef};
+use legion::*;
+use serde::{Deserialize, Serialize};
+
+use crate::components::*;
+use crate::input::InputState;
+use crate::particles::Particles;
+use crate::physics::{Physics, PhysicsSnapshot, RigidBodyHandle};
+use crate::resources::*;
+use crate::types::*;
+
+/// Bumped whenever the snapshot format changes, since old snapshots can't be read by new code.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+macro_rules! snapshot_components {
+    ($($field:ident: $component:ty),* $(,)?) => {
+        /// An entity's components. Everything the game creates has a `Transform`, and any of the
+        /// rest might be missing.
+        #[derive(Clone, Debug, Serialize, Deserialize)]
+        pub struct EntitySnapshot {
+            pub transform: Transform,
+            $(pub $field: Option<$component>,)*
+        }
+
+        impl EntitySnapshot {
+            fn capture(transform: Transform, entry: &EntryRef) -> Self {
+                EntitySnapshot {
+                    transform,
+                    $($field: entry.get_component::<$component>().ok().cloned(),)*
+                }
+            }
+
+            fn restore(self, entry: &mut Entry) {
+                $(
+                    if let Some(component) = self.$field {
+                        entry.add_component(component);
+                    }
+                )*
+            }
+        }
+    };
+}
+
+snapshot_components! {
+    sprite: Sprite,
+    composite_sprite: CompositeSprite,
+    animation: Animation,
+    one_shot: OneShot,
+    tag: EntityTag,
+    health: Health,
+    projectile: Projectile,
+    player: Player,
+    cull: Cull,
+    despawn: Despawn,
+    invulnerable: Invulnerable,
+    inventory: Inventory,
+    asteroid: Asteroid,
+    boss: Boss,
+    crystal: Crystal,
+    worker: Worker,
+    warrior: Warrior,
+    sinibomb: Sinibomb,
+    area_damage: AreaDamage,
+    emitter: Emitter,
+    body: RigidBodyHandle,
+}
+
+/// The resources that make up the simulation's state. Event queues are empty between steps,
+/// particles are only for show, and the window belongs to whoever is loading the snapshot, so none
+/// of those are saved.
+#[derive(Serialize, Deserialize)]
+struct ResourceSnapshot {
+    bounds: WorldBounds,
+    input: InputState,
+    view: ViewMatrix,
+    time: Time,
+    state: GameState,
+    session: Session,
+    director: WaveDirector,
+    rng: Random,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    version: u32,
+    entities: Vec<EntitySnapshot>,
+    resources: ResourceSnapshot,
+    physics: PhysicsSnapshot,
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Encoding(bincode::Error),
+    /// The snapshot was saved by a different version of the game.
+    Version {
+        found: u32,
+        expected: u32,
+    },
+    /// The physics world refers to an entity that isn't in the snapshot.
+    MissingEntity(usize),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::Encoding(e) => write!(f, "couldn't read snapshot: {}", e),
+            SnapshotError::Version { found, expected } => write!(
+                f,
+                "snapshot is version {}, but this game reads version {}",
+                found, expected
+            ),
+            SnapshotError::MissingEntity(i) => {
+                write!(f, "snapshot has a rigid body for missing entity {}", i)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl From<bincode::Error> for SnapshotError {
+    fn from(e: bincode::Error) -> Self {
+        SnapshotError::Encoding(e)
+    }
+}
+
+impl Snapshot {
+    /// Copies the simulation, leaving it as it was.
+    pub fn capture(world: &World, resources: &legion::Resources) -> Snapshot {
+        let mut index_of = HashMap::new();
+        let mut entities = vec![];
+        for (e, t) in <(Entity, &Transform)>::query().iter(world) {
+            if let Some(entry) = world.entry_ref(*e) {
+                index_of.insert(*e, entities.len());
+                entities.push(EntitySnapshot::capture(*t, &entry));
+            }
+        }
+
+        let time = *resources.get::<Time>().unwrap();
+        let view = ViewMatrix(resources.get::<ViewMatrix>().unwrap().0);
+        let input = resources.get::<InputState>().unwrap().clone();
+        let resources_snapshot = ResourceSnapshot {
+            bounds: *resources.get::<WorldBounds>().unwrap(),
+            input,
+            view,
+            time,
+            state: *resources.get::<GameState>().unwrap(),
+            session: *resources.get::<Session>().unwrap(),
+            director: *resources.get::<WaveDirector>().unwrap(),
+            rng: resources.get::<Random>().unwrap().clone(),
+        };
+
+        Snapshot {
+            version: SNAPSHOT_VERSION,
+            entities,
+            resources: resources_snapshot,
+            physics: resources.get::<Physics>().unwrap().snapshot(&index_of),
+        }
+    }
+
+    /// Puts the snapshot back into `world` and `resources`, replacing everything that was there.
+    pub fn restore(
+        self,
+        world: &mut World,
+        resources: &mut legion::Resources,
+    ) -> Result<(), SnapshotError> {
+        let count = self.entities.len();
+        if let Some(i) = self.physics.entity_indices().find(|i| *i >= count) {
+            return Err(SnapshotError::MissingEntity(i));
+        }
+
+        world.clear();
+        let mut entities = Vec::with_capacity(count);
+        for snapshot in self.entities {
+            let e = world.push((snapshot.transform,));
+            if let Some(mut entry) = world.entry(e) {
+                snapshot.restore(&mut entry);
+            }
+            entities.push(e);
+        }
+
+        let r = self.resources;
+        resources.insert(r.bounds);
+        resources.insert(r.input);
+        resources.insert(r.view);
+        resources.insert(r.time);
+        resources.insert(r.state);
+        resources.insert(r.session);
+        resources.insert(r.director);
+        resources.insert(r.rng);
+        resources.insert(InputEventQueue::default());
+        resources.insert(GameEventQueue::default());
+        resources.insert(SoundEventQueue::default());
+        resources.insert(Particles::default());
+        resources.insert(Physics::restore(self.physics, &entities));
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("snapshots should always serialize")
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Snapshot, SnapshotError> {
+        // check the version first, since a different version might not even deserialize
+        let version: u32 = bincode::deserialize(bytes)?;
+        if version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::Version {
+                found: version,
+                expected: SNAPSHOT_VERSION,
+            });
+        }
+        Ok(bincode::deserialize(bytes)?)
+    }
+}
@@ -18,6 +18,10 @@ impl Spritesheet {
         }
     }
 
+    pub fn sprite_count(&self) -> u32 {
+        self.rows * self.columns
+    }
+
     pub fn get_vertices(&self, sprite_index: u32) -> VertexQuad {
         compute_coords(sprite_index, self.columns, self.rows)
     }
@@ -0,0 +1,452 @@
+use std::time::Duration;
+
+use legion::systems::CommandBuffer;
+use legion::world::SubWorld;
+use legion::*;
+use na::{UnitComplex, Vector2};
+
+use crate::components::*;
+use crate::events::GameEvent;
+use crate::factories::{BulletBuilder, EntityBuilder};
+use crate::physics::{Physics, RigidBody, RigidBodyHandle};
+use crate::resources::*;
+use crate::types::*;
+
+const BOSS_SPEED: f32 = 4.0;
+// how quickly the boss turns its velocity towards the player, per second
+const BOSS_STEERING: f32 = 1.5;
+
+const WORKER_SPEED: f32 = 6.0;
+const WORKER_STEERING: f32 = 3.0;
+// how close a worker has to get to grab a crystal, or to hand it over to the Sinistar
+const PICKUP_RADIUS: f32 = 0.6;
+const DELIVERY_RADIUS: f32 = 2.0;
+// crystals this close to the player look closer to workers than they are, so workers swoop in and
+// steal them
+const STEAL_RADIUS: f32 = 8.0;
+const STEAL_BONUS: f32 = 10.0;
+// how close a worker has to get to the player to snatch a crystal from it
+const STEAL_CONTACT: f32 = 1.2;
+
+const WARRIOR_SPEED: f32 = 5.0;
+const WARRIOR_STEERING: f32 = 2.0;
+// warriors try to stay between STANDOFF - STANDOFF_BAND and STANDOFF + STANDOFF_BAND away from the
+// player, circling it in between
+const STANDOFF: f32 = 6.0;
+const STANDOFF_BAND: f32 = 1.5;
+// close enough that the player can see who's shooting at them
+const FIRE_RANGE: f32 = 8.0;
+const WARRIOR_RELOAD: Duration = Duration::from_millis(1500);
+const WARRIOR_BULLET_SPEED: f32 = 15.0;
+
+const SINIBOMB_THRUST: f32 = 3.0;
+const SINIBOMB_MAX_SPEED: f32 = 7.0;
+// how fast a sinibomb wants to turn per radian it's off course, and how hard it torques to get there
+const SINIBOMB_TURN_RATE: f32 = 4.0;
+const SINIBOMB_TORQUE: f32 = 0.2;
+const SINIBOMB_MAX_ANGULAR_VELOCITY: f32 = 4.0;
+
+fn position(t: &Transform) -> Vector2<f32> {
+    t.isometry.translation.vector.xy()
+}
+
+/// Turns a body's velocity towards `offset`. `steering` is how much of the difference is made up
+/// per second.
+fn steer(rb: &mut RigidBody, offset: Vector2<f32>, speed: f32, steering: f32, dt: f32) {
+    let desired = offset.try_normalize(1.0e-6).unwrap_or_else(Vector2::zeros) * speed;
+    rb.linvel += (desired - rb.linvel) * (steering * dt).min(1.0);
+}
+
+/// The angle that points a ship's nose (the sprite's +y) along `direction`.
+pub(super) fn facing(direction: Vector2<f32>) -> f32 {
+    (-direction.x).atan2(direction.y)
+}
+
+/// Points a ship's nose where it's going.
+fn face_velocity(rb: &mut RigidBody) {
+    if rb.linvel.norm() > 0.1 {
+        rb.position.rotation = UnitComplex::new(facing(rb.linvel));
+        rb.angvel = 0.0;
+    }
+}
+
+/// Where to aim a shot travelling at `speed` so that it meets a target at `offset` moving with
+/// `velocity`. Falls back to aiming straight at the target when the shot can't catch it.
+fn lead(offset: Vector2<f32>, velocity: Vector2<f32>, speed: f32) -> Vector2<f32> {
+    // solve |offset + velocity * t| = speed * t for the earliest t > 0
+    let a = velocity.norm_squared() - speed * speed;
+    let b = 2.0 * offset.dot(&velocity);
+    let c = offset.norm_squared();
+
+    let t = if a.abs() < 1.0e-6 {
+        -c / b
+    } else {
+        let discriminant = b * b - 4.0 * a * c;
+        if discriminant < 0.0 {
+            return offset;
+        }
+        let root = discriminant.sqrt();
+        let (t1, t2) = ((-b - root) / (2.0 * a), (-b + root) / (2.0 * a));
+        match (t1 > 0.0, t2 > 0.0) {
+            (true, true) => t1.min(t2),
+            (true, false) => t1,
+            (false, true) => t2,
+            (false, false) => return offset,
+        }
+    };
+    if t.is_finite() && t > 0.0 {
+        offset + velocity * t
+    } else {
+        offset
+    }
+}
+
+#[system]
+#[read_component(Player)]
+#[read_component(Transform)]
+#[read_component(Boss)]
+#[read_component(RigidBodyHandle)]
+pub(super) fn boss_chase(
+    world: &mut SubWorld,
+    #[resource] physics: &mut Physics,
+    #[resource] bounds: &WorldBounds,
+    #[resource] time: &Time,
+    #[resource] director: &WaveDirector,
+) {
+    let target = <(&Player, &Transform)>::query()
+        .iter(world)
+        .next()
+        .map(|(_, t)| position(t));
+
+    for (boss, handle) in <(&Boss, &RigidBodyHandle)>::query().iter(world) {
+        let rb = match physics.bodies.get_mut(*handle) {
+            Some(rb) => rb,
+            None => continue,
+        };
+        // the face should always be upright
+        rb.position.rotation = UnitComplex::identity();
+        rb.angvel = 0.0;
+
+        match (boss.state, target) {
+            (BossState::Active, Some(target)) => {
+                let offset = bounds.shortest_offset(rb.position.translation.vector, target);
+                let speed = BOSS_SPEED * director.difficulty().speed;
+                steer(rb, offset, speed, BOSS_STEERING, time.delta_seconds());
+            }
+            _ => rb.linvel = Vector2::zeros(),
+        }
+    }
+}
+
+#[system]
+#[read_component(Crystal)]
+#[read_component(Transform)]
+#[read_component(Player)]
+#[read_component(Boss)]
+#[read_component(Despawn)]
+#[read_component(RigidBodyHandle)]
+#[write_component(Worker)]
+#[write_component(Health)]
+#[write_component(Inventory)]
+pub(super) fn worker_ai(
+    world: &mut SubWorld,
+    cmd: &mut CommandBuffer,
+    #[resource] physics: &mut Physics,
+    #[resource] bounds: &WorldBounds,
+    #[resource] time: &Time,
+    #[resource] director: &WaveDirector,
+    #[resource] events: &mut GameEventQueue,
+) {
+    let dt = time.delta_seconds();
+    let speed = WORKER_SPEED * director.difficulty().speed;
+    let mut crystals: Vec<(Entity, Vector2<f32>)> = <(Entity, &Crystal, &Transform)>::query()
+        .filter(!component::<Despawn>())
+        .iter(world)
+        .map(|(e, _, t)| (*e, position(t)))
+        .collect();
+    let player = <(Entity, &Player, &Transform)>::query()
+        .iter(world)
+        .next()
+        .map(|(e, _, t)| (*e, position(t)));
+    let mut player_crystals = <(&Player, &Inventory)>::query()
+        .iter(world)
+        .next()
+        .map_or(0, |(_, i)| i.crystals);
+    let boss = <(Entity, &Boss, &Transform)>::query()
+        .iter(world)
+        .next()
+        .map(|(e, _, t)| (*e, position(t)));
+
+    let mut deliveries = 0;
+    let mut thieves = vec![];
+    for (e, worker, handle) in <(Entity, &mut Worker, &RigidBodyHandle)>::query().iter_mut(world) {
+        let rb = match physics.bodies.get_mut(*handle) {
+            Some(rb) => rb,
+            None => continue,
+        };
+        let here = rb.position.translation.vector;
+
+        let target = match worker.state {
+            WorkerState::Seeking => {
+                let nearest = crystals
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (_, p))| {
+                        let mut score = bounds.shortest_offset(here, *p).norm();
+                        if let Some((_, player)) = player {
+                            if bounds.shortest_offset(*p, player).norm() < STEAL_RADIUS {
+                                score -= STEAL_BONUS;
+                            }
+                        }
+                        (i, score)
+                    })
+                    .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+                    .map(|(i, _)| i);
+
+                let to_player = player.map(|(_, p)| bounds.shortest_offset(here, p));
+                match (nearest, to_player) {
+                    (_, Some(offset)) if player_crystals > 0 && offset.norm() < STEAL_CONTACT => {
+                        player_crystals -= 1;
+                        thieves.push(*e);
+                        worker.state = WorkerState::Delivering;
+                        Some(offset)
+                    }
+                    (Some(i), _) => {
+                        let (crystal, p) = crystals[i];
+                        let offset = bounds.shortest_offset(here, p);
+                        if offset.norm() < PICKUP_RADIUS {
+                            cmd.add_component(crystal, Despawn);
+                            crystals.swap_remove(i);
+                            worker.state = WorkerState::Delivering;
+                        }
+                        Some(offset)
+                    }
+                    // nothing floating around, so go after the player's crystals
+                    (None, Some(offset)) if player_crystals > 0 => Some(offset),
+                    (None, _) => None,
+                }
+            }
+            WorkerState::Delivering => boss.map(|(_, p)| {
+                let offset = bounds.shortest_offset(here, p);
+                if offset.norm() < DELIVERY_RADIUS {
+                    deliveries += 1;
+                    worker.state = WorkerState::Seeking;
+                }
+                offset
+            }),
+        };
+
+        match target {
+            Some(offset) => steer(rb, offset, speed, WORKER_STEERING, dt),
+            // nothing to do, so drift to a stop
+            None => steer(rb, Vector2::zeros(), 0.0, WORKER_STEERING, dt),
+        }
+        face_velocity(rb);
+    }
+
+    if let Some((player, _)) = player {
+        if let Some(mut entry) = world.entry_mut(player) {
+            if let Ok(inventory) = entry.get_component_mut::<Inventory>() {
+                for worker in thieves {
+                    if inventory.steal() {
+                        events.push(GameEvent::CrystalStolen { player, worker });
+                    }
+                }
+            }
+        }
+    }
+
+    let boss = match boss {
+        Some((e, _)) if deliveries > 0 => e,
+        _ => return,
+    };
+    if let Some(mut entry) = world.entry_mut(boss) {
+        let boss = entry.get_component::<Boss>().ok().copied();
+        if let (Some(boss), Ok(health)) = (boss, entry.get_component_mut::<Health>()) {
+            for _ in 0..deliveries {
+                boss.add_piece(health);
+            }
+            debug!("Workers delivered {} crystals", deliveries);
+        }
+    }
+}
+
+#[system]
+#[read_component(Player)]
+#[read_component(RigidBodyHandle)]
+#[write_component(Warrior)]
+pub(super) fn warrior_ai(
+    world: &mut SubWorld,
+    cmd: &mut CommandBuffer,
+    #[resource] physics: &mut Physics,
+    #[resource] bounds: &WorldBounds,
+    #[resource] time: &Time,
+    #[resource] director: &WaveDirector,
+) {
+    let dt = time.delta_seconds();
+    let difficulty = director.difficulty();
+    let speed = WARRIOR_SPEED * difficulty.speed;
+    let reload = WARRIOR_RELOAD.div_f32(difficulty.fire_rate);
+    let target = <(&Player, &RigidBodyHandle)>::query()
+        .iter(world)
+        .next()
+        .and_then(|(_, h)| physics.bodies.get(*h))
+        .map(|rb| (rb.position.translation.vector, rb.linvel));
+
+    let mut shots = vec![];
+    for (warrior, handle) in <(&mut Warrior, &RigidBodyHandle)>::query().iter_mut(world) {
+        let rb = match physics.bodies.get_mut(*handle) {
+            Some(rb) => rb,
+            None => continue,
+        };
+        warrior.reload = warrior.reload.checked_sub(time.delta).unwrap_or_default();
+
+        let (target, target_velocity) = match target {
+            Some(target) => target,
+            None => {
+                steer(rb, Vector2::zeros(), 0.0, WARRIOR_STEERING, dt);
+                continue;
+            }
+        };
+        let here = rb.position.translation.vector;
+        let offset = bounds.shortest_offset(here, target);
+        let distance = offset.norm();
+
+        let heading = if distance > STANDOFF + STANDOFF_BAND {
+            offset
+        } else if distance < STANDOFF - STANDOFF_BAND {
+            -offset
+        } else {
+            Vector2::new(-offset.y, offset.x)
+        };
+        steer(rb, heading, speed, WARRIOR_STEERING, dt);
+
+        // always face the shot, rather than where it's flying
+        let angle = facing(lead(offset, target_velocity, WARRIOR_BULLET_SPEED));
+        rb.position.rotation = UnitComplex::new(angle);
+        rb.angvel = 0.0;
+
+        if distance < FIRE_RANGE && warrior.reload == Duration::default() {
+            warrior.reload = reload;
+            shots.push(Transform::from(here).with_rotation(angle));
+        }
+    }
+
+    for t in shots {
+        BulletBuilder::starting_from(t, WARRIOR_BULLET_SPEED)
+            .fired_by(EntityTag::ENEMY)
+            .create_deferred(cmd, physics);
+    }
+}
+
+#[system]
+#[read_component(Boss)]
+#[read_component(CompositeSprite)]
+#[read_component(EntityTag)]
+#[read_component(Transform)]
+#[read_component(Despawn)]
+#[read_component(RigidBodyHandle)]
+#[write_component(Sinibomb)]
+pub(super) fn sinibomb_homing(
+    world: &mut SubWorld,
+    cmd: &mut CommandBuffer,
+    #[resource] physics: &mut Physics,
+    #[resource] bounds: &WorldBounds,
+    #[resource] time: &Time,
+) {
+    let dt = time.delta_seconds();
+    // every built piece of the Sinistar is a target, so bombs go for whichever is closest
+    let mut targets: Vec<Vector2<f32>> = <(&Boss, &CompositeSprite, &Transform)>::query()
+        .filter(!component::<Despawn>())
+        .iter(world)
+        .flat_map(|(_, sprite, t)| {
+            let center = position(t);
+            sprite
+                .parts
+                .iter()
+                .filter(|part| part.visible)
+                .map(move |part| center + part.offset)
+        })
+        .collect();
+    if targets.is_empty() {
+        targets = <(&EntityTag, &Transform)>::query()
+            .filter(!component::<Despawn>())
+            .iter(world)
+            .filter(|(tag, _)| **tag == EntityTag::ENEMY)
+            .map(|(_, t)| position(t))
+            .collect();
+    }
+
+    for (e, bomb, handle) in <(Entity, &mut Sinibomb, &RigidBodyHandle)>::query()
+        .filter(!component::<Despawn>())
+        .iter_mut(world)
+    {
+        bomb.fuse = bomb.fuse.checked_sub(time.delta).unwrap_or_default();
+        if bomb.fuse == Duration::default() {
+            debug!("Sinibomb {:?} fizzled out", e);
+            cmd.add_component(*e, Despawn);
+            continue;
+        }
+        let rb = match physics.bodies.get_mut(*handle) {
+            Some(rb) => rb,
+            None => continue,
+        };
+        let here = rb.position.translation.vector;
+        let offset = targets
+            .iter()
+            .map(|p| bounds.shortest_offset(here, *p))
+            .min_by(|a, b| a.norm().partial_cmp(&b.norm()).unwrap());
+
+        if let Some(offset) = offset {
+            // turn harder the further off course it is, easing off as it comes round so it doesn't
+            // overshoot
+            let error = rb
+                .position
+                .rotation
+                .angle_to(&UnitComplex::new(facing(offset)));
+            rb.apply_torque_impulse(
+                (error * SINIBOMB_TURN_RATE - rb.angvel) * SINIBOMB_TORQUE * dt,
+            );
+            rb.angvel = rb
+                .angvel
+                .min(SINIBOMB_MAX_ANGULAR_VELOCITY)
+                .max(-SINIBOMB_MAX_ANGULAR_VELOCITY);
+        }
+        let angle = rb.position.rotation.angle();
+        rb.apply_force(Vector2::new(-angle.sin(), angle.cos()) * SINIBOMB_THRUST);
+        if rb.linvel.norm() > SINIBOMB_MAX_SPEED {
+            rb.linvel.set_magnitude(SINIBOMB_MAX_SPEED);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lead_aims_at_still_targets() {
+        let offset = Vector2::new(3., 4.);
+        assert_eq!(lead(offset, Vector2::zeros(), 10.), offset);
+    }
+
+    #[test]
+    fn lead_meets_moving_targets() {
+        let offset = Vector2::new(10., 0.);
+        let velocity = Vector2::new(0., 5.);
+        let speed = 20.;
+
+        let aim = lead(offset, velocity, speed);
+        // the shot and the target should reach the aim point at the same time
+        let t = aim.norm() / speed;
+        assert!(((offset + velocity * t) - aim).norm() < 1.0e-3);
+        assert!(aim.y > 0.0);
+    }
+
+    #[test]
+    fn lead_gives_up_on_fast_targets() {
+        let offset = Vector2::new(10., 0.);
+        let velocity = Vector2::new(50., 0.);
+        assert_eq!(lead(offset, velocity, 20.), offset);
+    }
+}
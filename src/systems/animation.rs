@@ -0,0 +1,87 @@
+use legion::systems::CommandBuffer;
+use legion::world::SubWorld;
+use legion::*;
+
+use crate::components::*;
+use crate::events::GameEvent;
+use crate::factories::{EntityBuilder, ExplosionBuilder};
+use crate::physics::Physics;
+use crate::resources::*;
+use crate::types::*;
+
+// explosions are this much bigger than whatever blew up
+const EXPLOSION_SCALE: f32 = 1.5;
+const BOSS_EXPLOSION_SCALE: f32 = 8.0;
+
+#[system(for_each)]
+pub(super) fn animate(
+    e: &Entity,
+    animation: &mut Animation,
+    sprite: &mut Sprite,
+    #[resource] time: &Time,
+    #[resource] events: &mut GameEventQueue,
+) {
+    if animation.advance(time.delta) {
+        events.push(GameEvent::AnimationFinished { entity: *e });
+    }
+    sprite.index = animation.frame();
+}
+
+/// Removes one-shot entities once their animation has played.
+#[system]
+#[read_component(OneShot)]
+pub(super) fn one_shots(
+    world: &mut SubWorld,
+    cmd: &mut CommandBuffer,
+    #[resource] events: &GameEventQueue,
+) {
+    for e in events.get_mut().iter() {
+        let entity = match e {
+            GameEvent::AnimationFinished { entity } => *entity,
+            _ => continue,
+        };
+        let one_shot = world
+            .entry_ref(entity)
+            .map_or(false, |e| e.get_component::<OneShot>().is_ok());
+        if one_shot {
+            cmd.add_component(entity, Despawn);
+        }
+    }
+}
+
+/// Blows up everything that was destroyed this step, where it was when it went.
+#[system]
+#[read_component(Transform)]
+pub(super) fn explosions(
+    world: &mut SubWorld,
+    cmd: &mut CommandBuffer,
+    #[resource] physics: &mut Physics,
+    #[resource] events: &GameEventQueue,
+) {
+    let mut builder = ExplosionBuilder::default();
+    for e in events.get_mut().iter() {
+        let (entity, tag, position) = match e {
+            GameEvent::Destroyed {
+                entity,
+                tag,
+                position,
+                ..
+            } => (*entity, *tag, *position),
+            _ => continue,
+        };
+        let scale = if tag == EntityTag::BOSS {
+            BOSS_EXPLOSION_SCALE
+        } else {
+            // the destroyed entity is still around until the end of the step
+            world
+                .entry_ref(entity)
+                .and_then(|e| e.into_component::<Transform>().ok())
+                .map_or(1., |t| t.scale.x.max(1.))
+                * EXPLOSION_SCALE
+        };
+        builder = builder.add_explosion(position.translation.vector, scale);
+    }
+    if !builder.is_empty() {
+        builder.create_deferred(cmd, physics);
+    }
+}
@@ -0,0 +1,23 @@
+use legion::*;
+
+use crate::audio::SoundEvent;
+use crate::events::GameEvent;
+use crate::types::*;
+
+/// Sounds for things that already have a game event. Systems without one push their sounds
+/// themselves.
+#[system]
+pub(super) fn event_sounds(
+    #[resource] events: &GameEventQueue,
+    #[resource] sounds: &SoundEventQueue,
+) {
+    for e in events.get_mut().iter() {
+        let sound = match e {
+            GameEvent::Damaged { remaining, .. } if *remaining > 0 => SoundEvent::Hit,
+            GameEvent::Destroyed { .. } => SoundEvent::Explosion,
+            GameEvent::CrystalCollected { .. } => SoundEvent::Pickup,
+            _ => continue,
+        };
+        sounds.push(sound);
+    }
+}
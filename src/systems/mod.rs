@@ -1,30 +1,60 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::time::Duration;
 
-use instant::Instant;
 use legion::systems::CommandBuffer;
 use legion::world::SubWorld;
 use legion::*;
-use na::Vector2;
+use na::{Isometry2, UnitComplex, Vector2};
+use rand::Rng;
 
+use crate::audio::SoundEvent;
 use crate::components::*;
 use crate::constants::SPRITES_PER_HALF_SCREEN;
 use crate::event_queue::Drain;
-use crate::factories::{BulletBuilder, EntityBuilder};
-use crate::input::{InputEvent, InputState, Key, KeyState};
+use crate::events::GameEvent;
+use crate::factories::{
+    AsteroidBuilder, BossBuilder, BulletBuilder, CrystalBuilder, EntityBuilder, SinibombBuilder,
+};
+use crate::input::{
+    Action, ControlScheme, InputEvent, InputMap, InputState, KeyState, TouchLayout, TouchPhase,
+};
 use crate::physics::{Physics, Proximity, RigidBodyHandle};
 use crate::resources::*;
 use crate::types::*;
 
+mod ai;
+mod animation;
+mod audio;
+mod particles;
+mod session;
+mod waves;
+
 const MAX_VELOCITY: f32 = 10.0;
 const MAX_ANGULAR_VELOCITY: f32 = 2.0;
-const FRICTION: f32 = 50.0;
+// fraction of the ship's velocity lost per second while not accelerating
+const FRICTION: f32 = 1.2;
+const TURN_TORQUE: f32 = 30.0;
+// how hard a mouse-aimed ship turns for each radian it's off from the cursor
+const AIM_TURN_RATE: f32 = 4.0;
+const THRUST: f32 = 100.0;
+const SHOT_COOLDOWN: Duration = Duration::from_millis(300);
+const BOMB_COOLDOWN: Duration = Duration::from_millis(1000);
+// how fast asteroid fragments fly away from where their parent was, on top of its velocity
+const FRAGMENT_SPREAD: f32 = 2.0;
+// odds of a crystal breaking off an asteroid when it's shot, and how fast it drifts away
+const CRYSTAL_CHANCE: f64 = 0.3;
+const CRYSTAL_SPEED: f32 = 1.0;
 
 #[system]
 #[read_component(EntityTag)]
 #[read_component(Projectile)]
-fn physics(world: &mut SubWorld, cmd: &mut CommandBuffer, #[resource] physics: &mut Physics) {
-    physics.step();
+fn physics(
+    world: &mut SubWorld,
+    #[resource] physics: &mut Physics,
+    #[resource] time: &Time,
+    #[resource] events: &mut GameEventQueue,
+) {
+    physics.step(time.delta_seconds());
 
     for e in physics.proximity_events().iter() {
         if e.new_status == Proximity::Intersecting {
@@ -33,30 +63,32 @@ fn physics(world: &mut SubWorld, cmd: &mut CommandBuffer, #[resource] physics: &
             let tag2: Option<&EntityTag> =
                 world.entry_ref(e.e2).and_then(|e| e.into_component().ok());
 
-            match (tag1, tag2) {
-                (Some(&EntityTag::PROJECTILE), Some(&tag)) => {
-                    if let Some(proj) = world
-                        .entry_ref(e.e1)
-                        .and_then(|e| e.into_component::<Projectile>().ok())
-                    {
-                        if proj.can_hit & tag == tag {
-                            info!("Hit!: {:?}", e.e2);
-                            cmd.remove(e.e1);
-                        }
-                    }
+            let (projectile, target, tag) = match (tag1, tag2) {
+                (Some(&EntityTag::PLAYER), Some(&EntityTag::CRYSTAL)) => {
+                    events.push(GameEvent::CrystalCollected {
+                        player: e.e1,
+                        crystal: e.e2,
+                    });
+                    continue;
                 }
-                (Some(&tag), Some(&EntityTag::PROJECTILE)) => {
-                    if let Some(proj) = world
-                        .entry_ref(e.e2)
-                        .and_then(|e| e.into_component::<Projectile>().ok())
-                    {
-                        if proj.can_hit & tag == tag {
-                            info!("Hit!: {:?}", e.e1);
-                            cmd.remove(e.e2);
-                        }
-                    }
+                (Some(&EntityTag::CRYSTAL), Some(&EntityTag::PLAYER)) => {
+                    events.push(GameEvent::CrystalCollected {
+                        player: e.e2,
+                        crystal: e.e1,
+                    });
+                    continue;
+                }
+                (Some(&EntityTag::PROJECTILE), Some(&tag)) => (e.e1, e.e2, tag),
+                (Some(&tag), Some(&EntityTag::PROJECTILE)) => (e.e2, e.e1, tag),
+                (_, _) => continue,
+            };
+            if let Some(proj) = world
+                .entry_ref(projectile)
+                .and_then(|e| e.into_component::<Projectile>().ok())
+            {
+                if proj.can_hit & tag == tag {
+                    events.push(GameEvent::Hit { projectile, target });
                 }
-                (_, _) => {}
             }
         }
     }
@@ -68,9 +100,9 @@ fn physics(world: &mut SubWorld, cmd: &mut CommandBuffer, #[resource] physics: &
 #[system(for_each)]
 fn physics_transform(t: &mut Transform, handle: &RigidBodyHandle, #[resource] physics: &Physics) {
     // updates transforms with information from the physics system.
-    let rb = physics.bodies.get(*handle).unwrap();
-
-    t.set_isometry_2d(rb.position);
+    if let Some(rb) = physics.bodies.get(*handle) {
+        t.set_isometry_2d(rb.position);
+    }
 }
 
 #[system(for_each)]
@@ -94,12 +126,19 @@ fn culling(
         || pos.y > SPRITES_PER_HALF_SCREEN / dims.aspect_ratio
     {
         debug!("Culling bullet {:?} (pos: {:?}", e, pos);
-        cmd.remove(*e);
+        cmd.add_component(*e, Despawn);
     }
 }
 
+/// Applies the step's input events, then works out which actions they add up to.
 #[system]
-fn input(#[resource] input_queue: &mut InputEventQueue, #[resource] input_state: &mut InputState) {
+fn input(
+    #[resource] input_queue: &mut InputEventQueue,
+    #[resource] input_state: &mut InputState,
+    #[resource] input_map: &InputMap,
+    #[resource] dims: &WindowDimensions,
+) {
+    let mut start_requested = false;
     for e in input_queue.get_mut().drain() {
         match e {
             InputEvent::KeyboardEvent {
@@ -113,10 +152,39 @@ fn input(#[resource] input_queue: &mut InputEventQueue, #[resource] input_state:
                     input_state.release_key(code)
                 }
             }
-            // TODO: mouse events
-            _ => {}
+            InputEvent::MouseEvent {
+                button,
+                state,
+                x,
+                y,
+            } => {
+                input_state.move_cursor(x, y);
+                if state == KeyState::Pressed {
+                    input_state.press_button(button)
+                } else {
+                    input_state.release_button(button)
+                }
+            }
+            InputEvent::MouseMoveEvent { x, y } => input_state.move_cursor(x, y),
+            InputEvent::GamepadButtonEvent { button, state } => {
+                if state == KeyState::Pressed {
+                    input_state.press_gamepad_button(button)
+                } else {
+                    input_state.release_gamepad_button(button)
+                }
+            }
+            InputEvent::GamepadAxisEvent { axis, value } => input_state.set_axis(axis, value),
+            InputEvent::TouchEvent { id, phase, x, y } => match phase {
+                TouchPhase::Started => input_state.touch_start(id, (x, y)),
+                TouchPhase::Moved => input_state.touch_move(id, (x, y)),
+                TouchPhase::Ended => input_state.touch_end(id),
+            },
+            InputEvent::StartGameEvent => start_requested = true,
         }
     }
+    input_state.set_start_requested(start_requested);
+    input_state.update_touch_controls(&TouchLayout::for_window(dims.w, dims.h));
+    input_state.update_actions(input_map);
 }
 
 #[system(for_each)]
@@ -125,7 +193,10 @@ fn world_wrap(
     #[resource] bounds: &WorldBounds,
     #[resource] physics: &mut Physics,
 ) {
-    let mut rb = physics.bodies.get_mut(*handle).unwrap();
+    let rb = match physics.bodies.get_mut(*handle) {
+        Some(rb) => rb,
+        None => return,
+    };
     let v = &mut rb.position.translation.vector;
     let bounds = bounds.as_f32();
 
@@ -141,51 +212,97 @@ fn world_wrap(
 #[system]
 #[read_component(Player)]
 #[read_component(Transform)]
+#[write_component(Inventory)]
+#[allow(clippy::too_many_arguments)]
 fn player_shoot(
     world: &mut SubWorld,
     cmd: &mut CommandBuffer,
     #[resource] input_state: &InputState,
     #[resource] physics: &mut Physics,
-    #[state] last_shot: &mut Instant,
+    #[resource] time: &Time,
+    #[resource] sounds: &SoundEventQueue,
+    #[state] last_shot: &mut Option<Duration>,
+    #[state] last_bomb: &mut Option<Duration>,
 ) {
-    for (_, t) in <(&Player, &Transform)>::query().iter(world) {
-        let now = Instant::now();
-        if input_state.is_pressed(Key::Space) && now - *last_shot > Duration::from_millis(300) {
-            let builder = BulletBuilder::starting_from(*t, 30.0);
-            debug!("bullet: {:?}", builder.components()[0]);
-            let e = cmd.push(builder.components()[0]);
-            cmd.add_component(e, builder.create_physics(physics, &[e])[0]);
-
-            *last_shot = now;
+    for (_, t, inventory) in <(&Player, &Transform, &mut Inventory)>::query().iter_mut(world) {
+        let ready = last_shot.map_or(true, |last| time.elapsed - last >= SHOT_COOLDOWN);
+        if input_state.is_action_pressed(Action::Fire) && ready {
+            BulletBuilder::starting_from(*t, 30.0).create_deferred(cmd, physics);
+            sounds.push(SoundEvent::Fire);
+
+            *last_shot = Some(time.elapsed);
+        }
+
+        let ready = last_bomb.map_or(true, |last| time.elapsed - last >= BOMB_COOLDOWN);
+        if input_state.is_action_pressed(Action::Bomb) && ready && inventory.launch() {
+            SinibombBuilder::starting_from(*t).create_deferred(cmd, physics);
+            sounds.push(SoundEvent::Fire);
+            debug!("Launched a sinibomb, {} left", inventory.bombs);
+
+            *last_bomb = Some(time.elapsed);
         }
     }
 }
 
 #[system(for_each)]
+#[allow(clippy::too_many_arguments)]
 fn player_input(
     _p: &Player,
     handle: &mut RigidBodyHandle,
+    exhaust: Option<&mut Emitter>,
     #[resource] input_state: &InputState,
+    #[resource] input_map: &InputMap,
+    #[resource] dims: &WindowDimensions,
+    #[resource] view: &ViewMatrix,
     #[resource] physics: &mut Physics,
+    #[resource] time: &Time,
 ) {
-    // Ideally this should look at some kind of Key mapping data to figure out which keys do what.
-    let mut rb = physics.bodies.get_mut(*handle).unwrap();
-    if input_state.is_pressed(Key::Left) || input_state.is_pressed(Key::A) {
-        rb.apply_torque_impulse(0.5);
-    } else if rb.angvel > 0.0 {
-        rb.angvel -= rb.angvel * (1. / FRICTION);
-    }
-    if input_state.is_pressed(Key::Right) || input_state.is_pressed(Key::D) {
-        rb.apply_torque_impulse(-0.5);
-    } else if rb.angvel < 0.0 {
-        rb.angvel -= rb.angvel * (1. / FRICTION);
-    }
-    if input_state.is_pressed(Key::Up) || input_state.is_pressed(Key::W) {
+    let dt = time.delta_seconds();
+    let rb = match physics.bodies.get_mut(*handle) {
+        Some(rb) => rb,
+        None => return,
+    };
+    let cursor = match input_map.scheme {
+        ControlScheme::MouseAim => input_state
+            .cursor()
+            .and_then(|cursor| view.screen_to_world(dims, cursor)),
+        ControlScheme::Rotate => None,
+    };
+    if let Some(cursor) = cursor {
+        // the view follows the ship, so the cursor is never across the world's edge from it
+        let offset = cursor - rb.position.translation.vector;
+        if offset.norm() > 0.1 {
+            let error = rb
+                .position
+                .rotation
+                .angle_to(&UnitComplex::new(ai::facing(offset)));
+            rb.apply_torque_impulse((error * AIM_TURN_RATE - rb.angvel) * TURN_TORQUE * dt);
+        }
+    } else {
+        // sticks turn and thrust as hard as they're pushed, keys always go all the way
+        let left = input_state.action_value(Action::RotateLeft);
+        if left > 0.0 {
+            rb.apply_torque_impulse(TURN_TORQUE * left * dt);
+        } else if rb.angvel > 0.0 {
+            rb.angvel -= rb.angvel * FRICTION * dt;
+        }
+        let right = input_state.action_value(Action::RotateRight);
+        if right > 0.0 {
+            rb.apply_torque_impulse(-TURN_TORQUE * right * dt);
+        } else if rb.angvel < 0.0 {
+            rb.angvel -= rb.angvel * FRICTION * dt;
+        }
+    }
+    let thrust = input_state.action_value(Action::Thrust);
+    if let Some(exhaust) = exhaust {
+        exhaust.intensity = thrust;
+    }
+    if thrust > 0.0 {
         let angle = rb.position.rotation.angle();
-        rb.apply_force(Vector2::new(100. * -angle.sin(), 100. * angle.cos()));
+        rb.apply_force(Vector2::new(-angle.sin(), angle.cos()) * THRUST * thrust);
     } else if rb.linvel.norm() > 0.0 {
         let m = rb.linvel.norm();
-        rb.linvel.set_magnitude(m - m / FRICTION);
+        rb.linvel.set_magnitude(m - m * FRICTION * dt);
     }
     let m = rb.linvel.norm();
     if m > MAX_VELOCITY {
@@ -196,6 +313,301 @@ fn player_input(
         .min(MAX_ANGULAR_VELOCITY)
         .max(-MAX_ANGULAR_VELOCITY);
 }
+#[system]
+#[read_component(EntityTag)]
+#[read_component(Projectile)]
+#[read_component(AreaDamage)]
+#[read_component(Invulnerable)]
+#[read_component(RigidBodyHandle)]
+#[read_component(Transform)]
+#[write_component(Health)]
+fn damage(
+    world: &mut SubWorld,
+    cmd: &mut CommandBuffer,
+    #[resource] physics: &Physics,
+    #[resource] bounds: &WorldBounds,
+    #[resource] events: &mut GameEventQueue,
+) {
+    let hits: Vec<(Entity, Entity)> = events
+        .get_mut()
+        .iter()
+        .filter_map(|e| match e {
+            GameEvent::Hit { projectile, target } => Some((*projectile, *target)),
+            _ => None,
+        })
+        .collect();
+
+    // a bullet can overlap several things in one step, and several bullets can finish off the same
+    // target, so remember what's already gone.
+    let mut removed = HashSet::new();
+    for (projectile, target) in hits {
+        if removed.contains(&projectile) || removed.contains(&target) {
+            continue;
+        }
+        let (damage, area) = match world.entry_ref(projectile) {
+            Some(entry) => match entry.get_component::<Projectile>() {
+                Ok(p) => (
+                    p.damage as u16,
+                    entry
+                        .get_component::<AreaDamage>()
+                        .ok()
+                        .map(|area| (*area, p.can_hit)),
+                ),
+                Err(_) => continue,
+            },
+            None => continue,
+        };
+        cmd.add_component(projectile, Despawn);
+        removed.insert(projectile);
+
+        let mut victims = vec![(target, damage)];
+        if let Some((area, can_hit)) = area {
+            let (center, _) = last_motion(world, physics, projectile);
+            let center = center.translation.vector;
+            victims.extend(
+                <(Entity, &EntityTag, &Transform, &Health)>::query()
+                    .iter(world)
+                    .filter(|(e, tag, t, _)| {
+                        **e != target
+                            && can_hit & **tag == **tag
+                            && bounds
+                                .shortest_offset(center, t.isometry.translation.vector.xy())
+                                .norm()
+                                < area.radius
+                    })
+                    .map(|(e, ..)| (*e, area.damage)),
+            );
+        }
+
+        for (victim, damage) in victims {
+            let invulnerable = world
+                .entry_ref(victim)
+                .map_or(false, |e| e.get_component::<Invulnerable>().is_ok());
+            if removed.contains(&victim) || invulnerable {
+                continue;
+            }
+            let remaining = world.entry_mut(victim).and_then(|mut e| {
+                e.get_component_mut::<Health>().ok().map(|h| {
+                    h.0 = h.0.saturating_sub(damage);
+                    h.0
+                })
+            });
+            let remaining = match remaining {
+                Some(r) => r,
+                None => continue,
+            };
+            events.push(GameEvent::Damaged {
+                entity: victim,
+                amount: damage,
+                remaining,
+            });
+
+            if remaining == 0 {
+                let tag = world
+                    .entry_ref(victim)
+                    .and_then(|e| e.into_component::<EntityTag>().ok())
+                    .copied()
+                    .unwrap_or_else(EntityTag::empty);
+                let (position, linvel) = last_motion(world, physics, victim);
+                info!("Destroyed {:?} ({:?})", victim, tag);
+                events.push(GameEvent::Destroyed {
+                    entity: victim,
+                    tag,
+                    position,
+                    linvel,
+                });
+                cmd.add_component(victim, Despawn);
+                removed.insert(victim);
+            }
+        }
+    }
+}
+
+/// Where an entity is and how fast it's going, from its rigid body if it has one.
+fn last_motion(world: &SubWorld, physics: &Physics, e: Entity) -> (Isometry2<f32>, Vector2<f32>) {
+    let entry = match world.entry_ref(e) {
+        Some(entry) => entry,
+        None => return (Isometry2::identity(), Vector2::zeros()),
+    };
+    if let Some(rb) = entry
+        .get_component::<RigidBodyHandle>()
+        .ok()
+        .and_then(|h| physics.bodies.get(*h))
+    {
+        return (rb.position, rb.linvel);
+    }
+    let position = entry
+        .get_component::<Transform>()
+        .map(|t| t.as_2d())
+        .unwrap_or_else(|_| Isometry2::identity());
+    (position, Vector2::zeros())
+}
+
+#[system]
+#[read_component(Asteroid)]
+fn fragment_asteroids(
+    world: &mut SubWorld,
+    cmd: &mut CommandBuffer,
+    #[resource] physics: &mut Physics,
+    #[resource] events: &GameEventQueue,
+    #[resource] rng: &mut Random,
+) {
+    let mut builder = AsteroidBuilder::default();
+    for e in events.get_mut().iter() {
+        let (entity, position, linvel) = match e {
+            GameEvent::Destroyed {
+                entity,
+                tag,
+                position,
+                linvel,
+            } if *tag == EntityTag::ASTEROID => (*entity, *position, *linvel),
+            _ => continue,
+        };
+        // the destroyed asteroid is still around until the end of the step
+        let size = match world
+            .entry_ref(entity)
+            .and_then(|e| e.into_component::<Asteroid>().ok())
+            .and_then(|a| a.size.smaller())
+        {
+            Some(size) => size,
+            None => continue,
+        };
+
+        for _ in 0..rng.0.gen_range(2, 4) {
+            let angle = rng.0.gen_range(0.0, std::f32::consts::PI * 2.0);
+            let direction = Vector2::new(angle.cos(), angle.sin());
+            let t = Transform::from(position.translation.vector + direction * size.radius());
+            builder = builder.add_moving_asteroid(t, size, linvel + direction * FRAGMENT_SPREAD);
+        }
+    }
+    if !builder.is_empty() {
+        builder.create_deferred(cmd, physics);
+    }
+}
+
+#[system]
+#[read_component(EntityTag)]
+#[read_component(Transform)]
+fn release_crystals(
+    world: &mut SubWorld,
+    cmd: &mut CommandBuffer,
+    #[resource] physics: &mut Physics,
+    #[resource] events: &GameEventQueue,
+    #[resource] rng: &mut Random,
+) {
+    let mut builder = CrystalBuilder::default();
+    for e in events.get_mut().iter() {
+        let entity = match e {
+            GameEvent::Damaged { entity, .. } => *entity,
+            _ => continue,
+        };
+        let entry = match world.entry_ref(entity) {
+            Some(entry) => entry,
+            None => continue,
+        };
+        let position = match (
+            entry.get_component::<EntityTag>(),
+            entry.get_component::<Transform>(),
+        ) {
+            (Ok(&EntityTag::ASTEROID), Ok(t)) => t.isometry.translation.vector.xy(),
+            _ => continue,
+        };
+        if !rng.0.gen_bool(CRYSTAL_CHANCE) {
+            continue;
+        }
+
+        let angle = rng.0.gen_range(0.0, std::f32::consts::PI * 2.0);
+        let direction = Vector2::new(angle.cos(), angle.sin());
+        builder = builder.add_crystal(position + direction, direction * CRYSTAL_SPEED);
+    }
+    if !builder.is_empty() {
+        builder.create_deferred(cmd, physics);
+    }
+}
+
+#[system]
+#[read_component(Despawn)]
+#[write_component(Inventory)]
+fn pickup(world: &mut SubWorld, cmd: &mut CommandBuffer, #[resource] events: &GameEventQueue) {
+    let pickups: Vec<(Entity, Entity)> = events
+        .get_mut()
+        .iter()
+        .filter_map(|e| match e {
+            GameEvent::CrystalCollected { player, crystal } => Some((*player, *crystal)),
+            _ => None,
+        })
+        .collect();
+
+    for (player, crystal) in pickups {
+        // a worker might have gotten to it first
+        let taken = world
+            .entry_ref(crystal)
+            .map_or(true, |e| e.get_component::<Despawn>().is_ok());
+        if taken {
+            continue;
+        }
+        if let Some(mut entry) = world.entry_mut(player) {
+            if let Ok(inventory) = entry.get_component_mut::<Inventory>() {
+                inventory.collect();
+                debug!("Collected a crystal: {:?}", inventory);
+                cmd.add_component(crystal, Despawn);
+            }
+        }
+    }
+}
+
+#[system(for_each)]
+fn boss_construction(
+    boss: &mut Boss,
+    health: &Health,
+    sprite: &mut CompositeSprite,
+    handle: Option<&RigidBodyHandle>,
+    #[resource] physics: &mut Physics,
+    #[resource] sounds: &SoundEventQueue,
+) {
+    if boss.state == BossState::Building && boss.is_complete(health) {
+        info!("The Sinistar is complete!");
+        boss.state = BossState::Active;
+        sounds.push(SoundEvent::BossRoar);
+    }
+
+    // pieces come and go with the boss's health
+    let built = boss.pieces_built(health);
+    for (i, part) in sprite.parts.iter_mut().enumerate() {
+        part.visible = i < built;
+    }
+
+    // and so do their colliders, which the body holds in the order the pieces are built
+    let handle = match handle {
+        Some(handle) => *handle,
+        None => return,
+    };
+    let colliders = match physics.bodies.get(handle) {
+        Some(rb) => rb.colliders().to_vec(),
+        None => return,
+    };
+    for piece in colliders.len()..built {
+        physics.add_collider(handle, BossBuilder::piece_collider(piece));
+    }
+    for collider in colliders.iter().skip(built).rev() {
+        physics.remove_collider(*collider);
+    }
+}
+
+#[system(for_each)]
+fn despawn(
+    cmd: &mut CommandBuffer,
+    e: &Entity,
+    _: &Despawn,
+    handle: Option<&RigidBodyHandle>,
+    #[resource] physics: &mut Physics,
+) {
+    if let Some(handle) = handle {
+        physics.remove(*handle);
+    }
+    cmd.remove(*e);
+}
+
 #[system(for_each)]
 fn player_position(_p: &Player, t: &Transform, #[resource] view: &mut ViewMatrix) {
     *view.0 = *t
@@ -207,30 +619,80 @@ fn player_position(_p: &Player, t: &Transform, #[resource] view: &mut ViewMatrix
 }
 
 #[system]
-fn fps(#[state] frame_count: &mut u64, #[state] last_call: &mut Instant) {
+fn fps(
+    #[resource] time: &Time,
+    #[state] frame_count: &mut u64,
+    #[state] since_last: &mut Duration,
+) {
     *frame_count += 1;
+    *since_last += time.frame_delta;
 
-    let elapsed = Instant::now() - *last_call;
-    if elapsed > Duration::from_secs(3) {
+    if *since_last > Duration::from_secs(3) {
         info!(
             "FPS: {:02.02}",
-            *frame_count as f64 / elapsed.as_secs() as f64
+            *frame_count as f64 / since_last.as_secs_f64()
         );
         *frame_count = 0;
-        *last_call = Instant::now();
+        *since_last = Duration::default();
     }
 }
 
+/// Systems that advance the simulation. These run once per fixed timestep while a game is in
+/// progress.
 pub fn init() -> Schedule {
     Schedule::builder()
         .add_system(input_system())
+        .add_system(session::pause_system())
         .add_system(player_input_system())
-        .add_system(player_shoot_system(Instant::now()))
+        .add_system(player_shoot_system(None, None))
+        .add_system(ai::worker_ai_system())
+        // crystals workers grab have to be gone before the player can fly into them too
+        .flush()
+        .add_system(ai::warrior_ai_system())
+        .add_system(ai::sinibomb_homing_system())
+        .add_system(boss_construction_system())
+        .add_system(ai::boss_chase_system())
         .add_system(physics_transform_system())
         .add_system(player_position_system())
         .add_system(physics_system())
         .add_system(world_wrap_system())
         .add_system(culling_system())
-        .add_system(fps_system(0, Instant::now()))
+        .add_system(damage_system())
+        .add_system(fragment_asteroids_system())
+        .add_system(release_crystals_system())
+        .add_system(pickup_system())
+        .add_system(session::scoring_system())
+        .add_system(session::session_state_system())
+        .add_system(session::respawn_system())
+        .add_system(session::invulnerability_system())
+        // checked before spawning, which won't show up until the end of the step
+        .add_system(waves::wave_progress_system())
+        .add_system(waves::spawn_wave_system())
+        .add_system(animation::explosions_system())
+        .add_system(animation::animate_system())
+        .add_system(animation::one_shots_system())
+        .add_system(particles::emitters_system())
+        .add_system(particles::particle_bursts_system())
+        .add_system(particles::update_particles_system())
+        .add_system(audio::event_sounds_system())
+        // apply the Despawn components added above, then remove those entities and their bodies
+        .flush()
+        .add_system(despawn_system())
+        .build()
+}
+
+/// Systems that run instead of the simulation on the attract and game over screens.
+pub fn init_attract() -> Schedule {
+    Schedule::builder()
+        .add_system(input_system())
+        .add_system(session::pause_system())
+        .add_system(session::wait_for_start_system())
+        .build()
+}
+
+/// Systems that run once per rendered frame, however many simulation steps it took.
+pub fn init_frame() -> Schedule {
+    Schedule::builder()
+        .add_system(fps_system(0, Duration::default()))
         .build()
 }
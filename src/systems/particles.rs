@@ -0,0 +1,110 @@
+use legion::world::SubWorld;
+use legion::*;
+use na::Vector2;
+
+use crate::components::*;
+use crate::events::GameEvent;
+use crate::particles::{ParticleStyle, Particles};
+use crate::physics::{Physics, RigidBodyHandle};
+use crate::resources::*;
+use crate::types::*;
+
+const EXPLOSION_PARTICLES: usize = 24;
+const BOSS_EXPLOSION_PARTICLES: usize = 200;
+const EXPLOSION_SPEED: f32 = 8.0;
+const SPARK_PARTICLES: usize = 6;
+const SPARK_SPEED: f32 = 10.0;
+
+#[system(for_each)]
+pub(super) fn emitters(
+    t: &Transform,
+    emitter: &mut Emitter,
+    handle: Option<&RigidBodyHandle>,
+    #[resource] physics: &Physics,
+    #[resource] time: &Time,
+    #[resource] particles: &mut Particles,
+) {
+    if emitter.intensity <= 0. {
+        emitter.owed = 0.;
+        return;
+    }
+    emitter.owed += emitter.rate * emitter.intensity * time.delta_seconds();
+
+    let at = t.as_2d();
+    let position = at.translation.vector + at.rotation * emitter.offset;
+    let direction = at.rotation.angle() + emitter.direction;
+    // particles carry on with whatever they came out of
+    let velocity = handle
+        .and_then(|h| physics.bodies.get(*h))
+        .map_or_else(Vector2::zeros, |rb| rb.linvel);
+    while emitter.owed >= 1. {
+        emitter.owed -= 1.;
+        particles.emit(
+            position,
+            velocity,
+            direction,
+            emitter.spread,
+            emitter.speed * emitter.intensity,
+            emitter.style,
+        );
+    }
+}
+
+/// Bursts of particles for things blowing up, and sparks where bullets hit.
+#[system]
+#[read_component(Transform)]
+pub(super) fn particle_bursts(
+    world: &mut SubWorld,
+    #[resource] events: &GameEventQueue,
+    #[resource] particles: &mut Particles,
+) {
+    for e in events.get_mut().iter() {
+        match e {
+            GameEvent::Destroyed {
+                tag,
+                position,
+                linvel,
+                ..
+            } => {
+                let count = if *tag == EntityTag::BOSS {
+                    BOSS_EXPLOSION_PARTICLES
+                } else {
+                    EXPLOSION_PARTICLES
+                };
+                particles.burst(
+                    position.translation.vector,
+                    *linvel,
+                    count,
+                    EXPLOSION_SPEED,
+                    ParticleStyle::EXPLOSION,
+                );
+            }
+            GameEvent::Hit { projectile, .. } => {
+                // the projectile is still around until the end of the step
+                let position = world
+                    .entry_ref(*projectile)
+                    .and_then(|e| e.into_component::<Transform>().ok())
+                    .map(|t| t.isometry.translation.vector.xy());
+                if let Some(position) = position {
+                    particles.burst(
+                        position,
+                        Vector2::zeros(),
+                        SPARK_PARTICLES,
+                        SPARK_SPEED,
+                        ParticleStyle::SPARKS,
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[system]
+pub(super) fn update_particles(
+    #[resource] time: &Time,
+    #[resource] bounds: &WorldBounds,
+    #[resource] particles: &mut Particles,
+) {
+    particles.update(time.delta, bounds);
+}
@@ -0,0 +1,154 @@
+use legion::systems::CommandBuffer;
+use legion::world::SubWorld;
+use legion::*;
+use na::Vector2;
+use rand::Rng;
+
+use crate::components::*;
+use crate::events::GameEvent;
+use crate::factories::{EntityBuilder, PlayerBuilder};
+use crate::input::{Action, InputState};
+use crate::physics::Physics;
+use crate::resources::*;
+use crate::types::*;
+
+// a respawned ship wants at least this much room around it
+const SAFE_DISTANCE: f32 = 6.0;
+// random spots to try when the middle of the world isn't safe
+const RESPAWN_ATTEMPTS: usize = 20;
+
+/// Starts a game from the attract screen or after a game over.
+#[system]
+pub(super) fn wait_for_start(
+    #[resource] input_state: &InputState,
+    #[resource] state: &mut GameState,
+) {
+    if !input_state.just_pressed(Action::Fire) && !input_state.start_requested() {
+        return;
+    }
+    match *state {
+        // the first ship is already waiting in the middle of the world
+        GameState::Attract => *state = GameState::Playing,
+        // what's left of the last game has to be cleared away first
+        GameState::GameOver => *state = GameState::Starting,
+        _ => return,
+    }
+    info!("Starting game: {:?}", state);
+}
+
+/// Pauses or unpauses a game in progress. Paused games run the attract schedule, which has this
+/// too, so that the game can be unpaused.
+#[system]
+pub(super) fn pause(#[resource] input_state: &InputState, #[resource] state: &mut GameState) {
+    if !input_state.just_pressed(Action::Pause) {
+        return;
+    }
+    *state = match *state {
+        GameState::Playing => GameState::Paused,
+        GameState::Paused => GameState::Playing,
+        state => state,
+    };
+    info!("Pause: {:?}", state);
+}
+
+#[system]
+pub(super) fn scoring(
+    #[resource] events: &GameEventQueue,
+    #[resource] time: &Time,
+    #[resource] state: &mut GameState,
+    #[resource] session: &mut Session,
+) {
+    for e in events.get_mut().iter() {
+        match e {
+            GameEvent::Destroyed { tag, .. } if *tag == EntityTag::PLAYER => {
+                *state = session.lose_life(time.elapsed);
+                info!("Player destroyed, {} lives left", session.lives);
+            }
+            GameEvent::Destroyed { tag, .. } => session.score += Session::points(*tag),
+            _ => {}
+        }
+    }
+}
+
+#[system]
+pub(super) fn session_state(
+    #[resource] time: &Time,
+    #[resource] session: &Session,
+    #[resource] state: &mut GameState,
+) {
+    let next = state.advance(time.elapsed, session.lives);
+    if next != *state {
+        info!("{:?} -> {:?}", state, next);
+        *state = next;
+    }
+}
+
+#[system]
+#[read_component(EntityTag)]
+#[read_component(Transform)]
+pub(super) fn respawn(
+    world: &mut SubWorld,
+    cmd: &mut CommandBuffer,
+    #[resource] physics: &mut Physics,
+    #[resource] bounds: &WorldBounds,
+    #[resource] rng: &mut Random,
+    #[resource] state: &mut GameState,
+) {
+    if *state != GameState::Respawning {
+        return;
+    }
+    let threats: Vec<Vector2<f32>> = <(&EntityTag, &Transform)>::query()
+        .iter(world)
+        .filter(|(tag, _)| tag.intersects(EntityTag::ENEMY_OR_ASTEROID | EntityTag::BOSS))
+        .map(|(_, t)| t.isometry.translation.vector.xy())
+        .collect();
+    let clearance = |p: Vector2<f32>| {
+        threats
+            .iter()
+            .map(|t| bounds.shortest_offset(p, *t).norm())
+            .fold(std::f32::INFINITY, f32::min)
+    };
+
+    // try the middle first, then wherever has the most room
+    let size = bounds.as_f32();
+    let mut best = size / 2.0;
+    let mut room = clearance(best);
+    for _ in 0..RESPAWN_ATTEMPTS {
+        if room >= SAFE_DISTANCE {
+            break;
+        }
+        let p = Vector2::new(rng.0.gen_range(0.0, size.x), rng.0.gen_range(0.0, size.y));
+        let r = clearance(p);
+        if r > room {
+            best = p;
+            room = r;
+        }
+    }
+
+    info!("Respawning player at {:?}", best);
+    for e in PlayerBuilder::starting_from(best.into()).create_deferred(cmd, physics) {
+        cmd.add_component(
+            e,
+            Invulnerable {
+                remaining: INVULNERABILITY,
+            },
+        );
+    }
+    *state = GameState::Playing;
+}
+
+#[system(for_each)]
+pub(super) fn invulnerability(
+    cmd: &mut CommandBuffer,
+    e: &Entity,
+    invulnerable: &mut Invulnerable,
+    #[resource] time: &Time,
+) {
+    invulnerable.remaining = invulnerable
+        .remaining
+        .checked_sub(time.delta)
+        .unwrap_or_default();
+    if invulnerable.remaining == Default::default() {
+        cmd.remove_component::<Invulnerable>(*e);
+    }
+}
@@ -0,0 +1,117 @@
+use legion::systems::CommandBuffer;
+use legion::world::SubWorld;
+use legion::*;
+use na::Vector2;
+use rand::Rng;
+
+use crate::components::*;
+use crate::events::GameEvent;
+use crate::factories::{AsteroidBuilder, EntityBuilder, WarriorBuilder, WorkerBuilder};
+use crate::physics::Physics;
+use crate::resources::*;
+use crate::types::*;
+
+// new arrivals stay at least this far from the player, so they don't appear on top of it
+const SPAWN_DISTANCE: f32 = 15.0;
+const SPAWN_ATTEMPTS: usize = 10;
+
+/// Somewhere random that's out of the player's way, if there's a player.
+fn spawn_point(
+    rng: &mut Random,
+    bounds: &WorldBounds,
+    player: Option<Vector2<f32>>,
+) -> Vector2<f32> {
+    let size = bounds.as_f32();
+    let mut p = Vector2::zeros();
+    for _ in 0..SPAWN_ATTEMPTS {
+        p = Vector2::new(rng.0.gen_range(0.0, size.x), rng.0.gen_range(0.0, size.y));
+        match player {
+            Some(player) if bounds.shortest_offset(p, player).norm() < SPAWN_DISTANCE => continue,
+            _ => break,
+        }
+    }
+    p
+}
+
+#[system]
+#[read_component(Player)]
+#[read_component(Asteroid)]
+#[read_component(Transform)]
+pub(super) fn spawn_wave(
+    world: &mut SubWorld,
+    cmd: &mut CommandBuffer,
+    #[resource] physics: &mut Physics,
+    #[resource] bounds: &WorldBounds,
+    #[resource] time: &Time,
+    #[resource] rng: &mut Random,
+    #[resource] director: &mut WaveDirector,
+) {
+    match director.next_wave_at {
+        Some(at) if time.elapsed >= at => director.next_wave_at = None,
+        _ => return,
+    }
+    let wave = director.plan();
+    info!("Wave {} ({:?}): {:?}", director.wave, director.zone(), wave);
+
+    let player = <(&Player, &Transform)>::query()
+        .iter(world)
+        .next()
+        .map(|(_, t)| t.isometry.translation.vector.xy());
+    let (mut asteroids, mut planetoids) = (0, 0);
+    for asteroid in <&Asteroid>::query().iter(world) {
+        match asteroid.size {
+            AsteroidSize::Planetoid => planetoids += 1,
+            _ => asteroids += 1,
+        }
+    }
+
+    let mut rocks = AsteroidBuilder::default();
+    for _ in asteroids..wave.asteroids {
+        rocks = rocks.add_asteroid(spawn_point(rng, bounds, player));
+    }
+    for _ in planetoids..wave.planetoids {
+        rocks = rocks.add_planetoid(spawn_point(rng, bounds, player));
+    }
+    if !rocks.is_empty() {
+        rocks.create_deferred(cmd, physics);
+    }
+
+    let mut workers = WorkerBuilder::default();
+    for _ in 0..wave.workers {
+        workers = workers.add_worker(spawn_point(rng, bounds, player));
+    }
+    workers.create_deferred(cmd, physics);
+
+    let mut warriors = WarriorBuilder::default();
+    for _ in 0..wave.warriors {
+        warriors = warriors.add_warrior(spawn_point(rng, bounds, player));
+    }
+    warriors.create_deferred(cmd, physics);
+}
+
+/// A wave is over once all of its enemies are gone.
+#[system]
+#[read_component(EntityTag)]
+#[read_component(Despawn)]
+pub(super) fn wave_progress(
+    world: &mut SubWorld,
+    #[resource] time: &Time,
+    #[resource] director: &mut WaveDirector,
+    #[resource] events: &mut GameEventQueue,
+) {
+    if director.next_wave_at.is_some() {
+        return;
+    }
+    let enemies = <&EntityTag>::query()
+        .filter(!component::<Despawn>())
+        .iter(world)
+        .filter(|tag| **tag == EntityTag::ENEMY)
+        .count();
+    if enemies == 0 {
+        info!("Wave {} complete", director.wave);
+        events.push(GameEvent::WaveCompleted {
+            wave: director.wave,
+        });
+        director.complete(time.elapsed);
+    }
+}
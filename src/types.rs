@@ -1,9 +1,34 @@
-use na::Matrix4;
+use na::{Matrix4, Vector2, Vector4};
+use serde::{Deserialize, Serialize};
 
+use crate::audio::SoundEvent;
 use crate::event_queue::SharedEventQueue;
+use crate::events::GameEvent;
 use crate::input::InputEvent;
+use crate::resources::WindowDimensions;
 
 pub type InputEventQueue = SharedEventQueue<InputEvent>;
+pub type GameEventQueue = SharedEventQueue<GameEvent>;
+pub type SoundEventQueue = SharedEventQueue<SoundEvent>;
 
-#[derive(Default)]
+#[derive(Default, Serialize, Deserialize)]
 pub struct ViewMatrix(pub Matrix4<f32>);
+
+impl ViewMatrix {
+    /// Where a point in the window, in pixels from the top left, is in the world. There's nowhere
+    /// to point at until the view has been set up.
+    pub fn screen_to_world(
+        &self,
+        dims: &WindowDimensions,
+        (x, y): (i32, i32),
+    ) -> Option<Vector2<f32>> {
+        let ndc = Vector4::new(
+            2. * x as f32 / dims.w as f32 - 1.,
+            1. - 2. * y as f32 / dims.h as f32,
+            0.,
+            1.,
+        );
+        let to_world = (dims.projection() * self.0).try_inverse()?;
+        Some((to_world * ndc).xy())
+    }
+}
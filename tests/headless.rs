@@ -0,0 +1,710 @@
+//! Runs the simulation without a window.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+use legion::*;
+use nalgebra::Vector2;
+use rand::Rng;
+
+use voidstar_lib::audio::{RecordingBackend, SoundEvent};
+use voidstar_lib::components::{
+    Asteroid, AsteroidSize, Boss, EntityTag, Health, Inventory, Invulnerable, OneShot, Player,
+    Projectile, Transform, Warrior,
+};
+use voidstar_lib::constants::FIXED_TIMESTEP;
+use voidstar_lib::factories::{
+    AsteroidBuilder, BossBuilder, BulletBuilder, CrystalBuilder, WarriorBuilder, WorkerBuilder,
+};
+use voidstar_lib::input::{
+    Action, Binding, ControlScheme, GamepadState, InputEvent, InputMap, Key, KeyState, TouchLayout,
+    TouchPhase,
+};
+use voidstar_lib::level::Level;
+use voidstar_lib::overlay::Overlay;
+use voidstar_lib::particles::Particles;
+use voidstar_lib::physics::{Physics, RigidBodyHandle};
+use voidstar_lib::replay::Replay;
+use voidstar_lib::resources::{
+    GameState, Random, Session, Time, WaveDirector, WindowDimensions, RESPAWN_DELAY,
+};
+use voidstar_lib::Game;
+
+/// A headless game that's past the attract screen.
+fn new_game() -> Game {
+    let mut game = Game::headless();
+    game.start();
+    // which is input like any other, so it's handled by the next step
+    game.step();
+    game
+}
+
+/// Lets the first wave arrive, so it doesn't show up in the middle of a test.
+fn skip_wave_arrival(game: &mut Game) {
+    game.step();
+}
+
+fn player_transform(game: &Game) -> Transform {
+    let mut query = <(&Player, &Transform)>::query();
+    let (_, t) = query
+        .iter(game.world())
+        .next()
+        .expect("the player should exist");
+    *t
+}
+
+fn press(game: &mut Game, code: Key) {
+    game.log_event(InputEvent::KeyboardEvent {
+        code,
+        state: KeyState::Pressed,
+        repeated: false,
+    });
+}
+
+#[test]
+fn headless_game_has_no_renderer() {
+    let game = Game::headless();
+    assert!(game.is_headless());
+}
+
+#[test]
+fn thrust_moves_the_player_forward() {
+    let mut game = new_game();
+    let start = player_transform(&game).isometry.translation.vector;
+
+    press(&mut game, Key::Up);
+    for _ in 0..30 {
+        game.step();
+    }
+
+    let end = player_transform(&game).isometry.translation.vector;
+    assert!(end.y > start.y, "{:?} should be above {:?}", end, start);
+}
+
+#[test]
+fn thrusting_leaves_a_trail_of_exhaust() {
+    let mut game = new_game();
+    let particles = |game: &Game| game.resources().get::<Particles>().unwrap().len();
+    for _ in 0..10 {
+        game.step();
+    }
+    assert_eq!(particles(&game), 0);
+
+    press(&mut game, Key::Up);
+    for _ in 0..10 {
+        game.step();
+    }
+    assert!(particles(&game) > 0);
+}
+
+#[test]
+fn turning_rotates_the_player() {
+    let mut game = new_game();
+
+    press(&mut game, Key::Left);
+    for _ in 0..10 {
+        game.step();
+    }
+
+    assert!(player_transform(&game).as_2d().rotation.angle() > 0.0);
+}
+
+#[test]
+fn advance_runs_whole_fixed_steps() {
+    let mut game = new_game();
+    let frame = |game: &Game| game.resources().get::<Time>().unwrap().frame;
+    let start = frame(&game);
+
+    game.advance(FIXED_TIMESTEP * 5 / 2);
+    assert_eq!(frame(&game), start + 2);
+
+    // the leftover half step carries over to the next frame
+    game.advance(FIXED_TIMESTEP * 3 / 4);
+    assert_eq!(frame(&game), start + 3);
+}
+
+fn asteroid_health(game: &Game) -> u16 {
+    <(&EntityTag, &Health)>::query()
+        .iter(game.world())
+        .filter(|(tag, _)| **tag == EntityTag::ASTEROID)
+        .map(|(_, h)| h.0)
+        .sum()
+}
+
+#[test]
+fn shooting_damages_asteroids() {
+    let mut game = new_game();
+    skip_wave_arrival(&mut game);
+    game.spawn(AsteroidBuilder::default().add_asteroid((50., 30.)));
+    let before = asteroid_health(&game);
+
+    // the player starts facing up, straight at it
+    press(&mut game, Key::Space);
+    for _ in 0..30 {
+        game.step();
+    }
+
+    assert!(asteroid_health(&game) < before);
+}
+
+#[test]
+fn large_asteroids_break_into_medium_ones() {
+    let mut game = new_game();
+    skip_wave_arrival(&mut game);
+    let asteroids = |game: &Game| -> Vec<(Entity, AsteroidSize)> {
+        <(Entity, &Asteroid)>::query()
+            .iter(game.world())
+            .map(|(e, a)| (*e, a.size))
+            .collect()
+    };
+    let wave = asteroids(&game);
+    let is_new = |e: &Entity| !wave.iter().any(|(w, _)| w == e);
+
+    game.spawn(AsteroidBuilder::default().add_asteroid((50., 30.)));
+    let (target, _) = asteroids(&game)
+        .into_iter()
+        .find(|(e, _)| is_new(e))
+        .unwrap();
+    // one shot is enough
+    if let Some(mut entry) = game.world_mut().entry(target) {
+        entry.get_component_mut::<Health>().unwrap().0 = 1;
+    }
+    let gun = Transform::from((50., 26.));
+    game.spawn(BulletBuilder::starting_from(gun, 30.));
+    for _ in 0..10 {
+        game.step();
+    }
+
+    let fragments: Vec<AsteroidSize> = asteroids(&game)
+        .into_iter()
+        .filter(|(e, _)| is_new(e))
+        .map(|(e, size)| {
+            assert_ne!(e, target, "the asteroid should have been destroyed");
+            size
+        })
+        .collect();
+    assert!(
+        fragments.len() == 2 || fragments.len() == 3,
+        "{:?}",
+        fragments
+    );
+    assert!(
+        fragments.iter().all(|size| *size == AsteroidSize::Medium),
+        "{:?}",
+        fragments
+    );
+}
+
+#[test]
+fn despawned_entities_release_their_bodies() {
+    let mut game = new_game();
+
+    // bullets either hit an asteroid or fly offscreen and get culled
+    press(&mut game, Key::Space);
+    for _ in 0..180 {
+        game.step();
+    }
+
+    let entities = <&RigidBodyHandle>::query().iter(game.world()).count();
+    let bodies = game.resources().get::<Physics>().unwrap().bodies.len();
+    assert_eq!(entities, bodies);
+}
+
+#[test]
+fn workers_bring_crystals_to_the_boss() {
+    let mut game = new_game();
+    let boss_health = |game: &Game| -> u16 {
+        let mut query = <(&Boss, &Health)>::query();
+        query.iter(game.world()).map(|(_, h)| h.0).next().unwrap()
+    };
+    let before = boss_health(&game);
+
+    // right next to the boss, which starts at (20, 10)
+    game.spawn(CrystalBuilder::default().add_crystal((20., 13.), Vector2::zeros()));
+    game.spawn(WorkerBuilder::default().add_worker((20., 14.)));
+    for _ in 0..120 {
+        game.step();
+    }
+
+    assert!(boss_health(&game) > before);
+}
+
+#[test]
+fn warriors_turn_and_fire_at_the_player() {
+    let mut game = new_game();
+    skip_wave_arrival(&mut game);
+    // off to the right of the player, in range and facing up
+    game.spawn(WarriorBuilder::default().add_warrior((56., 25.)));
+    for _ in 0..5 {
+        game.step();
+    }
+
+    let player = player_transform(&game).isometry.translation.vector.xy();
+    // the wave has warriors of its own, but they're further out
+    let (_, warrior) = <(&Warrior, &Transform)>::query()
+        .iter(game.world())
+        .find(|(_, t)| (t.isometry.translation.vector.xy() - player).norm() < 8.)
+        .unwrap();
+    let at = warrior.as_2d();
+    let nose = at.rotation * Vector2::y();
+    let to_player = (player - at.translation.vector).normalize();
+    assert!(
+        nose.dot(&to_player) > 0.95,
+        "{:?} should point at {:?}",
+        nose,
+        to_player
+    );
+
+    // and there's a shot on its way between the two
+    let shots = <(&Projectile, &Transform)>::query()
+        .iter(game.world())
+        .filter(|(p, t)| {
+            let at = t.isometry.translation.vector;
+            p.owner == EntityTag::ENEMY && at.x > 50. && at.x < 56. && (at.y - 25.).abs() < 1.
+        })
+        .count();
+    assert_eq!(shots, 1);
+}
+
+#[test]
+fn bullets_pass_through_unbuilt_boss_pieces() {
+    let mut game = new_game();
+    skip_wave_arrival(&mut game);
+    // just the middle piece to start with, a little way above the player
+    game.spawn(BossBuilder::starting_from(Transform::from((50., 29.))));
+    let boss_health = |game: &Game| -> u16 {
+        <(&Boss, &Transform, &Health)>::query()
+            .iter(game.world())
+            .filter(|(_, t, _)| t.isometry.translation.vector.x > 40.)
+            .map(|(_, _, h)| h.0)
+            .sum()
+    };
+    let shoot = |game: &mut Game, x: f32| {
+        let gun = Transform::from((x, 25.));
+        game.spawn(BulletBuilder::starting_from(gun, 30.));
+        for _ in 0..12 {
+            game.step();
+        }
+    };
+    let before = boss_health(&game);
+
+    // up the column of pieces to the right of the middle one
+    shoot(&mut game, 51.);
+    assert_eq!(boss_health(&game), before);
+    let past_the_boss = <(&Projectile, &Transform)>::query()
+        .iter(game.world())
+        .filter(|(_, t)| t.isometry.translation.vector.y > 31.)
+        .count();
+    assert_eq!(past_the_boss, 1);
+
+    shoot(&mut game, 50.);
+    assert!(boss_health(&game) < before);
+}
+
+#[test]
+fn flying_into_a_crystal_picks_it_up() {
+    let mut game = new_game();
+
+    game.spawn(CrystalBuilder::default().add_crystal((50., 25.3), Vector2::zeros()));
+    for _ in 0..5 {
+        game.step();
+    }
+
+    let mut query = <(&Player, &Inventory)>::query();
+    let (_, inventory) = query.iter(game.world()).next().unwrap();
+    assert_eq!(inventory.bombs, 1);
+}
+
+#[test]
+fn workers_steal_crystals_from_the_player() {
+    let mut game = new_game();
+    for (_, inventory) in <(&Player, &mut Inventory)>::query().iter_mut(game.world_mut()) {
+        inventory.collect();
+    }
+
+    game.spawn(WorkerBuilder::default().add_worker((50., 26.)));
+    game.step();
+
+    let mut query = <(&Player, &Inventory)>::query();
+    let (_, inventory) = query.iter(game.world()).next().unwrap();
+    assert_eq!(inventory.crystals, 0);
+    // the bomb went with it
+    assert_eq!(inventory.bombs, 0);
+}
+
+#[test]
+fn sinibombs_damage_everything_nearby() {
+    let mut game = new_game();
+    skip_wave_arrival(&mut game);
+    for (_, inventory) in <(&Player, &mut Inventory)>::query().iter_mut(game.world_mut()) {
+        inventory.bombs = 1;
+    }
+    // one right in front of the player to set the bomb off, and one off to the side
+    game.spawn(
+        AsteroidBuilder::default()
+            .add_asteroid((50., 27.))
+            .add_asteroid((52., 27.5)),
+    );
+    let before = asteroid_health(&game);
+
+    press(&mut game, Key::B);
+    for _ in 0..10 {
+        game.step();
+    }
+
+    let mut query = <(&Player, &Inventory)>::query();
+    let (_, inventory) = query.iter(game.world()).next().unwrap();
+    assert_eq!(inventory.bombs, 0);
+    // the direct hit plus the blast
+    assert!(asteroid_health(&game) <= before - 5);
+}
+
+#[test]
+fn explosions_clear_up_after_themselves() {
+    let mut game = new_game();
+    skip_wave_arrival(&mut game);
+    game.spawn(AsteroidBuilder::default().add_moving_asteroid(
+        (50., 27.),
+        AsteroidSize::Small,
+        Vector2::zeros(),
+    ));
+    let explosions = |game: &Game| <&OneShot>::query().iter(game.world()).count();
+
+    press(&mut game, Key::Space);
+    for _ in 0..10 {
+        game.step();
+    }
+    game.log_event(InputEvent::KeyboardEvent {
+        code: Key::Space,
+        state: KeyState::Released,
+        repeated: false,
+    });
+    assert_eq!(explosions(&game), 1);
+
+    for _ in 0..60 {
+        game.step();
+    }
+    assert_eq!(explosions(&game), 0);
+}
+
+#[test]
+fn shooting_things_makes_noise() {
+    let mut game = new_game();
+    let audio = RecordingBackend::default();
+    game.set_audio_backend(Box::new(audio.clone()));
+    skip_wave_arrival(&mut game);
+    assert!(audio.played().is_empty());
+
+    game.spawn(AsteroidBuilder::default().add_moving_asteroid(
+        (50., 27.),
+        AsteroidSize::Small,
+        Vector2::zeros(),
+    ));
+    press(&mut game, Key::Space);
+    for _ in 0..10 {
+        game.step();
+    }
+
+    let played = audio.played();
+    assert_eq!(played.first(), Some(&SoundEvent::Fire));
+    assert!(played.contains(&SoundEvent::Explosion));
+}
+
+#[test]
+fn nothing_moves_until_the_game_starts() {
+    let mut game = Game::headless();
+    let start = player_transform(&game).isometry.translation.vector;
+
+    press(&mut game, Key::Up);
+    for _ in 0..30 {
+        game.step();
+    }
+    assert_eq!(player_transform(&game).isometry.translation.vector, start);
+
+    press(&mut game, Key::Space);
+    game.step();
+    assert_eq!(
+        *game.resources().get::<GameState>().unwrap(),
+        GameState::Playing
+    );
+}
+
+#[test]
+fn pausing_stops_the_clock() {
+    let mut game = new_game();
+    skip_wave_arrival(&mut game);
+    press(&mut game, Key::P);
+    game.step();
+    assert_eq!(
+        *game.resources().get::<GameState>().unwrap(),
+        GameState::Paused
+    );
+
+    let elapsed = game.resources().get::<Time>().unwrap().elapsed;
+    let start = player_transform(&game).isometry.translation.vector;
+    press(&mut game, Key::Up);
+    for _ in 0..30 {
+        game.step();
+    }
+    assert_eq!(game.resources().get::<Time>().unwrap().elapsed, elapsed);
+    assert_eq!(player_transform(&game).isometry.translation.vector, start);
+}
+
+#[test]
+fn rebound_keys_fly_the_ship() {
+    let mut game = new_game();
+    let mut map = InputMap::default();
+    map.rebind(Action::Thrust, vec![Binding::Key(Key::K)]);
+    game.set_input_map(map);
+    let start = player_transform(&game).isometry.translation.vector;
+
+    press(&mut game, Key::K);
+    for _ in 0..30 {
+        game.step();
+    }
+    let end = player_transform(&game).isometry.translation.vector;
+    assert!(end.y > start.y, "{:?} should be above {:?}", end, start);
+}
+
+#[test]
+fn mouse_aimed_ships_turn_toward_the_cursor() {
+    let mut game = new_game();
+    let mut map = InputMap::default();
+    map.scheme = ControlScheme::MouseAim;
+    game.set_input_map(map);
+    // the view has to follow the ship before there's anywhere to point at
+    game.step();
+
+    let dims = *game.resources().get::<WindowDimensions>().unwrap();
+    game.log_event(InputEvent::MouseMoveEvent {
+        x: dims.w as i32,
+        y: dims.h as i32 / 2,
+    });
+    for _ in 0..30 {
+        game.step();
+    }
+    // facing right is a quarter turn clockwise from the ship's starting heading
+    let angle = player_transform(&game).isometry.rotation.angle();
+    assert!(angle < -0.5, "{} should be turning clockwise", angle);
+}
+
+#[test]
+fn sticks_turn_as_hard_as_theyre_pushed() {
+    let turn = |deflection: f32| {
+        let mut game = new_game();
+        let mut state = GamepadState::default();
+        state.axes[0] = -deflection;
+        game.update_gamepad(state);
+        for _ in 0..10 {
+            game.step();
+        }
+        player_transform(&game).isometry.rotation.angle()
+    };
+
+    let (nudge, full) = (turn(0.4), turn(1.0));
+    assert!(nudge > 0.0, "{} should be turning left", nudge);
+    assert!(
+        full > nudge,
+        "{} should be further round than {}",
+        full,
+        nudge
+    );
+    assert_eq!(turn(0.1), 0.0, "the dead zone shouldn't turn the ship");
+}
+
+#[test]
+fn the_touch_stick_flies_the_ship() {
+    let mut game = new_game();
+    let dims = *game.resources().get::<WindowDimensions>().unwrap();
+    let stick = TouchLayout::for_window(dims.w, dims.h).stick;
+    let start = player_transform(&game).isometry.translation.vector;
+
+    let (x, y) = (stick.x as i32, stick.y as i32);
+    for (phase, y) in [(TouchPhase::Started, y), (TouchPhase::Moved, y - 100)].iter() {
+        game.log_event(InputEvent::TouchEvent {
+            id: 7,
+            phase: *phase,
+            x,
+            y: *y,
+        });
+    }
+    for _ in 0..30 {
+        game.step();
+    }
+    let end = player_transform(&game).isometry.translation.vector;
+    assert!(end.y > start.y, "{:?} should be above {:?}", end, start);
+}
+
+#[test]
+fn the_hud_shows_once_the_game_starts() {
+    let mut game = Game::headless();
+    let hud_text = |game: &Game| {
+        Overlay::gather(game.world(), game.resources())
+            .texts
+            .into_iter()
+            .map(|text| text.content)
+            .collect::<Vec<_>>()
+    };
+    assert!(!hud_text(&game).iter().any(|t| t.starts_with("SCORE")));
+
+    game.start();
+    game.step();
+    game.resources_mut().get_mut::<Session>().unwrap().score = 150;
+    let text = hud_text(&game);
+    assert!(text.contains(&"SCORE 000150".to_string()), "{:?}", text);
+    assert!(text.contains(&"WAVE 1".to_string()), "{:?}", text);
+}
+
+#[test]
+fn destroyed_players_respawn_invulnerable() {
+    let mut game = new_game();
+    for (_, health) in <(&Player, &mut Health)>::query().iter_mut(game.world_mut()) {
+        health.0 = 1;
+    }
+    // bullets come out ahead of whatever fires them, so this one lands right on the player
+    let gun = Transform::from((50., 23.75));
+    game.spawn(BulletBuilder::starting_from(gun, 0.).fired_by(EntityTag::ENEMY));
+    for _ in 0..5 {
+        game.step();
+    }
+    assert_eq!(<&Player>::query().iter(game.world()).count(), 0);
+    assert_eq!(game.resources().get::<Session>().unwrap().lives, 2);
+
+    let steps = RESPAWN_DELAY.as_secs() * 60 + 5;
+    for _ in 0..steps {
+        game.step();
+    }
+    assert_eq!(
+        *game.resources().get::<GameState>().unwrap(),
+        GameState::Playing
+    );
+    let mut query = <(&Player, &Invulnerable)>::query();
+    assert_eq!(query.iter(game.world()).count(), 1);
+}
+
+#[test]
+fn games_after_a_game_over_start_from_scratch() {
+    let mut game = new_game();
+    skip_wave_arrival(&mut game);
+    for _ in 0..60 {
+        game.step();
+    }
+    game.spawn(AsteroidBuilder::default().add_asteroid((50., 30.)));
+    game.resources_mut().get_mut::<WaveDirector>().unwrap().wave = 3;
+    *game.resources_mut().get_mut::<GameState>().unwrap() = GameState::GameOver;
+
+    game.start();
+    game.step();
+    assert_eq!(
+        *game.resources().get::<GameState>().unwrap(),
+        GameState::Playing
+    );
+    assert_eq!(game.resources().get::<WaveDirector>().unwrap().wave, 1);
+    // just what the level starts with, until the first wave arrives
+    assert_eq!(asteroid_health(&game), 0);
+    assert_eq!(<&Player>::query().iter(game.world()).count(), 1);
+    let entities = <&RigidBodyHandle>::query().iter(game.world()).count();
+    let bodies = game.resources().get::<Physics>().unwrap().bodies.len();
+    assert_eq!(entities, bodies);
+}
+
+#[test]
+fn clearing_a_wave_brings_the_next_one() {
+    let mut game = new_game();
+    skip_wave_arrival(&mut game);
+
+    let enemies: Vec<Entity> = <(Entity, &EntityTag)>::query()
+        .iter(game.world())
+        .filter(|(_, tag)| **tag == EntityTag::ENEMY)
+        .map(|(e, _)| *e)
+        .collect();
+    assert!(!enemies.is_empty());
+    for e in enemies {
+        game.world_mut().remove(e);
+    }
+    game.step();
+
+    let director = *game.resources().get::<WaveDirector>().unwrap();
+    assert_eq!(director.wave, 2);
+    assert!(director.next_wave_at.is_some());
+}
+
+#[test]
+fn snapshots_restore_an_equivalent_world() {
+    let mut game = new_game();
+    press(&mut game, Key::Up);
+    for _ in 0..30 {
+        game.step();
+    }
+
+    let saved = game.save();
+    let mut restored = Game::headless();
+    restored.load(&saved).unwrap();
+
+    let bodies = |game: &Game| game.resources().get::<Physics>().unwrap().bodies.len();
+    assert_eq!(bodies(&restored), bodies(&game));
+    assert_eq!(
+        <&RigidBodyHandle>::query().iter(restored.world()).count(),
+        <&RigidBodyHandle>::query().iter(game.world()).count()
+    );
+
+    // both carry on the same way, still thrusting
+    for _ in 0..30 {
+        game.step();
+        restored.step();
+    }
+    let expected = player_transform(&game).isometry.translation.vector;
+    let actual = player_transform(&restored).isometry.translation.vector;
+    assert!(
+        (expected - actual).norm() < 1.0e-3,
+        "{:?} != {:?}",
+        actual,
+        expected
+    );
+}
+
+#[test]
+fn saving_leaves_the_game_alone() {
+    let mut game = new_game();
+    for _ in 0..30 {
+        game.step();
+    }
+    let next_number = |game: &Game| -> u64 {
+        let mut rng = game.resources().get::<Random>().unwrap().clone();
+        rng.0.gen()
+    };
+    let expected = next_number(&game);
+
+    let saved = game.save();
+    assert_eq!(next_number(&game), expected);
+    let mut restored = Game::headless();
+    restored.load(&saved).unwrap();
+    assert_eq!(next_number(&restored), expected);
+}
+
+#[test]
+fn replays_play_out_the_same_way() {
+    let mut game = Game::headless_seeded(&Level::default(), 7);
+    game.start_recording();
+    game.start();
+    press(&mut game, Key::Up);
+    press(&mut game, Key::Space);
+    for i in 0..120 {
+        if i == 60 {
+            press(&mut game, Key::Left);
+        }
+        game.step();
+    }
+    let replay = Replay::from_bytes(&game.finish_recording().unwrap()).unwrap();
+
+    // starting the game was recorded along with everything else
+    let mut replayed = Game::headless_from_replay(replay);
+    while !replayed.replay_finished() {
+        replayed.step();
+    }
+
+    assert_eq!(replayed.replay_diverged_at(), None);
+    assert_eq!(
+        player_transform(&replayed).isometry,
+        player_transform(&game).isometry
+    );
+}
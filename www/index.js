@@ -14,6 +14,42 @@ canvas.addEventListener("mouseup", (e) => {
   e.stopPropagation();
   game.log_mouseup_event(e);
 });
+canvas.addEventListener("mousemove", (e) => {
+  e.stopPropagation();
+  game.log_mousemove_event(e);
+});
+// the secondary button launches sinibombs, so keep the menu out of the way
+canvas.addEventListener("contextmenu", (e) => {
+  e.preventDefault();
+});
+// touches would otherwise scroll the page and turn into mouse events as well
+const touchOptions = { passive: false };
+canvas.addEventListener(
+  "touchstart",
+  (e) => {
+    e.preventDefault();
+    game.log_touchstart_event(e);
+  },
+  touchOptions
+);
+canvas.addEventListener(
+  "touchmove",
+  (e) => {
+    e.preventDefault();
+    game.log_touchmove_event(e);
+  },
+  touchOptions
+);
+for (const type of ["touchend", "touchcancel"]) {
+  canvas.addEventListener(
+    type,
+    (e) => {
+      e.preventDefault();
+      game.log_touchend_event(e);
+    },
+    touchOptions
+  );
+}
 canvas.addEventListener("keydown", (e) => {
   e.stopPropagation();
   game.log_keydown_event(e);
@@ -23,7 +59,23 @@ canvas.addEventListener("keyup", (e) => {
   game.log_keyup_event(e);
 });
 
+// gamepads can't be listened to, only polled, so the first one is passed in every frame
+const pollGamepad = () => {
+  const pad = Array.from(navigator.getGamepads ? navigator.getGamepads() : []).find(
+    (pad) => pad && pad.connected && pad.mapping === "standard"
+  );
+  if (pad) {
+    game.log_gamepad_state(
+      Float32Array.from(pad.axes),
+      Float32Array.from(pad.buttons, (button) => button.value)
+    );
+  } else {
+    game.log_gamepad_state(new Float32Array(), new Float32Array());
+  }
+};
+
 const renderLoop = () => {
+  pollGamepad();
   game.tick();
   requestAnimationFrame(renderLoop);
 };